@@ -0,0 +1,93 @@
+/// # CPU から見たバス
+///
+/// CPU はこのトレイトを通してメモリや I/O にアクセスします。
+/// NES では `Machine` が実装していて, CPU 単体のテストでは `RamBus` を使います
+///
+/// | Method | Description |
+/// | - | - |
+/// | `read` | 読み出し (レジスタの読み出しなどで状態が変わることがある) |
+/// | `write` | 書き込み |
+/// | `peek` | 状態を変えずに `read` と同じ値を返す (デバッガ, 逆アセンブラ用) |
+/// | `tick` | 1サイクル進める (サイクル単位で実行するときバスアクセスごとに呼ばれる) |
+pub trait Bus {
+  fn read(&mut self, addr: usize) -> u8;
+  fn write(&mut self, addr: usize, val: u8);
+  fn peek(&self, addr: usize) -> u8;
+
+  fn tick(&mut self) {}
+
+  // 割り込みの信号
+  fn nmi(&self) -> bool {
+    false
+  }
+
+  fn irq(&self) -> bool {
+    false
+  }
+
+  // 前回の呼び出しから OAM DMA が発生したか
+  fn take_oam_dma(&mut self) -> bool {
+    false
+  }
+}
+
+#[cfg(test)]
+const RAM_SIZE: usize = 0x10000; // 64KiB
+
+/// # 64KiB の RAM だけのバス
+///
+/// `$0000 ~ $ffff` がすべて読み書きできる RAM になっていて, PPU などは繋がっていません。
+/// 汎用 6502 のテストプログラムを CPU 単体で動かすのに使います
+#[cfg(test)]
+pub struct RamBus {
+  pub ram: Vec<u8>,
+  pub cycles: u64,
+  pub nmi: bool,
+  pub irq: bool,
+}
+
+#[cfg(test)]
+impl RamBus {
+  pub fn new() -> Self {
+    Self {
+      ram: vec![0; RAM_SIZE],
+      cycles: 0,
+      nmi: false,
+      irq: false,
+    }
+  }
+
+  // addr から data を書き込む
+  pub fn load(&mut self, addr: usize, data: &[u8]) {
+    for (i, val) in data.iter().enumerate() {
+      self.ram[(addr + i) % RAM_SIZE] = *val;
+    }
+  }
+}
+
+#[cfg(test)]
+impl Bus for RamBus {
+  fn read(&mut self, addr: usize) -> u8 {
+    self.ram[addr % RAM_SIZE]
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    self.ram[addr % RAM_SIZE] = val;
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    self.ram[addr % RAM_SIZE]
+  }
+
+  fn tick(&mut self) {
+    self.cycles += 1;
+  }
+
+  fn nmi(&self) -> bool {
+    self.nmi
+  }
+
+  fn irq(&self) -> bool {
+    self.irq
+  }
+}
@@ -0,0 +1,342 @@
+use super::mapper::Mirroring;
+use std::error;
+use std::fmt;
+use std::io;
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_RAM_SIZE: usize = 0x2000; // 8KiB
+
+/// ヘッダの形式
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+  Ines,
+  Nes2,
+}
+
+/// CPU/PPU のタイミング (地域)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Timing {
+  Ntsc,
+  Pal,
+  MultiRegion,
+  Dendy,
+}
+
+/// ROM を読み込めなかった理由
+#[derive(Debug)]
+pub enum LoadError {
+  /// ファイルを開けない, 読めない
+  Io(io::Error),
+  /// "NES\x1a" で始まっていない
+  BadMagic,
+  /// ヘッダ (16byte) に満たない
+  TruncatedHeader(usize),
+  /// trainer, PRG-ROM, CHR-ROM がファイルの途中で切れている
+  TruncatedTrainer { expected: usize, actual: usize },
+  TruncatedPrg { expected: usize, actual: usize },
+  TruncatedChr { expected: usize, actual: usize },
+  /// PRG-ROM が無い
+  NoPrgRom,
+  /// NES 2.0 の指数表記のサイズが大きすぎる
+  InvalidSize(u8),
+  /// 対応していないマッパー
+  UnsupportedMapper(u16),
+  /// マッパーが切り替えられるバンク数よりも ROM が大きい
+  OversizedBanks { rom: &'static str, bytes: usize, max: usize },
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LoadError::Io(err) => write!(f, "{}", err),
+      LoadError::BadMagic => write!(f, "expect \"NES\\x1a\""),
+      LoadError::TruncatedHeader(actual) => write!(f, "header is truncated ({} bytes)", actual),
+      LoadError::TruncatedTrainer { expected, actual } => {
+        write!(f, "trainer is truncated (expect {} bytes, got {})", expected, actual)
+      }
+      LoadError::TruncatedPrg { expected, actual } => {
+        write!(f, "PRG-ROM is truncated (expect {} bytes, got {})", expected, actual)
+      }
+      LoadError::TruncatedChr { expected, actual } => {
+        write!(f, "CHR-ROM is truncated (expect {} bytes, got {})", expected, actual)
+      }
+      LoadError::NoPrgRom => write!(f, "PRG-ROM size is 0"),
+      LoadError::InvalidSize(byte) => write!(f, "invalid ROM size 0x{:>02x}", byte),
+      LoadError::UnsupportedMapper(mapper) => write!(f, "unsupported mapper {}", mapper),
+      LoadError::OversizedBanks { rom, bytes, max } => {
+        write!(f, "{} is too large for this mapper (0x{:x} bytes, max 0x{:x})", rom, bytes, max)
+      }
+    }
+  }
+}
+
+impl error::Error for LoadError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      LoadError::Io(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for LoadError {
+  fn from(err: io::Error) -> Self {
+    LoadError::Io(err)
+  }
+}
+
+/// # iNES ヘッダ
+///
+/// iNES 1.0 と NES 2.0 の両方の形式を読みます
+///
+/// | Byte | Description |
+/// | - | - |
+/// | 0 ~ 3 | "NES\x1a" |
+/// | 4 | PRG-ROM のサイズ (16KiB 単位) |
+/// | 5 | CHR-ROM のサイズ (8KiB 単位) |
+/// | 6 | Flags 6 (ミラーリング, バッテリー, trainer, 4画面, マッパー番号の下位4bit) |
+/// | 7 | Flags 7 (NES 2.0 の識別, マッパー番号の上位4bit) |
+/// | 8 ~ 15 | iNES: PRG-RAM のサイズ, TV 方式 / NES 2.0: サブマッパー, ROM/RAM のサイズ, タイミング |
+///
+/// https://wiki.nesdev.com/w/index.php/INES
+/// https://wiki.nesdev.com/w/index.php/NES_2.0
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+  pub format: Format,
+  pub mapper: u16,
+  pub submapper: u8,
+  pub mirroring: Mirroring,
+  pub battery: bool,
+  pub trainer: bool,
+  pub timing: Timing,
+
+  pub prg_rom_bytes: usize,
+  pub chr_rom_bytes: usize,
+  pub prg_ram_bytes: usize,
+  pub prg_nvram_bytes: usize,
+  pub chr_ram_bytes: usize,
+  pub chr_nvram_bytes: usize,
+}
+
+impl Header {
+  pub fn parse(buffer: &[u8]) -> Result<Self, LoadError> {
+    if buffer.len() < 4 || buffer[0..4] != [0x4e, 0x45, 0x53, 0x1a] {
+      return Err(LoadError::BadMagic);
+    }
+    if buffer.len() < HEADER_SIZE {
+      return Err(LoadError::TruncatedHeader(buffer.len()));
+    }
+
+    let flags_6 = buffer[6];
+    let flags_7 = buffer[7];
+    let format = if flags_7 & 0x0c == 0x08 { Format::Nes2 } else { Format::Ines };
+
+    let mirroring = if flags_6 & 0x08 != 0 {
+      Mirroring::FourScreen
+    } else if flags_6 & 0x01 != 0 {
+      Mirroring::Vertical
+    } else {
+      Mirroring::Horizontal
+    };
+    let battery = flags_6 & 0x02 != 0;
+    let trainer = flags_6 & 0x04 != 0;
+
+    let header = match format {
+      Format::Nes2 => {
+        let rom_size = |lsb: u8, msb: u8, unit: usize| -> Result<usize, LoadError> {
+          if msb == 0x0f {
+            // 指数表記: 2^E * (MM * 2 + 1)
+            let exponent = lsb >> 2;
+            let multiplier = (lsb & 0x03) as usize * 2 + 1;
+            if exponent > 32 {
+              return Err(LoadError::InvalidSize(lsb));
+            }
+            Ok((1 << exponent) * multiplier)
+          } else {
+            Ok((((msb as usize) << 8) | lsb as usize) * unit)
+          }
+        };
+        // 0 なら無し, それ以外は 64 << n byte
+        let ram_size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+
+        Self {
+          format,
+          mapper: (((buffer[8] & 0x0f) as u16) << 8) | (flags_7 & 0xf0) as u16 | (flags_6 >> 4) as u16,
+          submapper: buffer[8] >> 4,
+          mirroring,
+          battery,
+          trainer,
+          timing: match buffer[12] & 0x03 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+          },
+
+          prg_rom_bytes: rom_size(buffer[4], buffer[9] & 0x0f, 0x4000)?,
+          chr_rom_bytes: rom_size(buffer[5], buffer[9] >> 4, 0x2000)?,
+          prg_ram_bytes: ram_size(buffer[10] & 0x0f),
+          prg_nvram_bytes: ram_size(buffer[10] >> 4),
+          chr_ram_bytes: ram_size(buffer[11] & 0x0f),
+          chr_nvram_bytes: ram_size(buffer[11] >> 4),
+        }
+      }
+
+      Format::Ines => {
+        // 古いツールが 12 ~ 15 byte目にゴミを書いている場合は flags 7 を信用しない
+        let mapper_high = if buffer[12..16].iter().all(|&byte| byte == 0) { flags_7 & 0xf0 } else { 0 };
+        let chr_rom_bytes = buffer[5] as usize * 0x2000;
+        // 0 は 8KiB として扱う
+        let prg_ram_bytes = (buffer[8].max(1) as usize) * PRG_RAM_SIZE;
+
+        Self {
+          format,
+          mapper: (mapper_high | (flags_6 >> 4)) as u16,
+          submapper: 0,
+          mirroring,
+          battery,
+          trainer,
+          timing: if buffer[9] & 0x01 == 0 { Timing::Ntsc } else { Timing::Pal },
+
+          prg_rom_bytes: buffer[4] as usize * 0x4000,
+          chr_rom_bytes,
+          prg_ram_bytes: if battery { 0 } else { prg_ram_bytes },
+          prg_nvram_bytes: if battery { prg_ram_bytes } else { 0 },
+          // CHR-ROM が無いときは 8KiB の CHR-RAM
+          chr_ram_bytes: if chr_rom_bytes == 0 { 0x2000 } else { 0 },
+          chr_nvram_bytes: 0,
+        }
+      }
+    };
+
+    if header.prg_rom_bytes == 0 {
+      return Err(LoadError::NoPrgRom);
+    }
+
+    Ok(header)
+  }
+
+  // ファイル先頭から PRG-ROM までのオフセット
+  pub fn prg_offset(&self) -> usize {
+    HEADER_SIZE + if self.trainer { TRAINER_SIZE } else { 0 }
+  }
+
+  pub fn chr_offset(&self) -> usize {
+    self.prg_offset() + self.prg_rom_bytes
+  }
+}
+
+/// # カセット
+///
+/// iNES ファイルから読み込んだ ROM とヘッダの情報を保持します
+pub struct Cartridge {
+  pub header: Header,
+
+  pub prg_rom: Vec<u8>,
+  pub chr_rom: Vec<u8>,
+  pub prg_ram: Vec<u8>,
+  /// CHR-ROM が無いカセットは代わりに書き込み可能な CHR-RAM を持つ
+  pub chr_ram: Vec<u8>,
+
+  /// 最後にセーブしてから PRG-RAM が書き換えられたか
+  pub prg_ram_dirty: bool,
+}
+
+impl Cartridge {
+  pub fn new(header: Header, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    let prg_ram_bytes = header.prg_ram_bytes + header.prg_nvram_bytes;
+    let chr_ram_bytes = if chr_rom.is_empty() {
+      // NES 2.0 でサイズが指定されていなくても 8KiB は用意する
+      (header.chr_ram_bytes + header.chr_nvram_bytes).max(0x2000)
+    } else {
+      0
+    };
+
+    Self {
+      header,
+      prg_rom,
+      chr_rom,
+      prg_ram: vec![0; prg_ram_bytes],
+      chr_ram: vec![0; chr_ram_bytes],
+
+      prg_ram_dirty: false,
+    }
+  }
+
+  // iNES ファイルの中身からカセットを作る
+  pub fn from_bytes(buffer: &[u8]) -> Result<Self, LoadError> {
+    let header = Header::parse(buffer)?;
+
+    let prg_addr = header.prg_offset();
+    let chr_addr = header.chr_offset();
+    if buffer.len() < prg_addr {
+      return Err(LoadError::TruncatedTrainer {
+        expected: TRAINER_SIZE,
+        actual: buffer.len() - HEADER_SIZE,
+      });
+    }
+    if buffer.len() < chr_addr {
+      return Err(LoadError::TruncatedPrg {
+        expected: header.prg_rom_bytes,
+        actual: buffer.len() - prg_addr,
+      });
+    }
+    if buffer.len() < chr_addr + header.chr_rom_bytes {
+      return Err(LoadError::TruncatedChr {
+        expected: header.chr_rom_bytes,
+        actual: buffer.len() - chr_addr,
+      });
+    }
+
+    let prg_rom = buffer[prg_addr..chr_addr].to_vec();
+    let chr_rom = buffer[chr_addr..chr_addr + header.chr_rom_bytes].to_vec();
+
+    Ok(Self::new(header, prg_rom, chr_rom))
+  }
+
+  // bank_size 単位で区切った bank 番目のバンクを読む
+  // 存在しないバンク番号は ROM のサイズでミラーされる
+  pub fn read_prg(&self, bank: usize, bank_size: usize, offset: usize) -> u8 {
+    if self.prg_rom.is_empty() {
+      return 0;
+    }
+
+    self.prg_rom[(bank * bank_size + offset) % self.prg_rom.len()]
+  }
+
+  // CHR-RAM のカセットも同じようにバンクを切り替えられる
+  pub fn read_chr(&self, bank: usize, bank_size: usize, offset: usize) -> u8 {
+    let chr = if self.chr_ram.is_empty() { &self.chr_rom } else { &self.chr_ram };
+    if chr.is_empty() {
+      return 0;
+    }
+
+    chr[(bank * bank_size + offset) % chr.len()]
+  }
+
+  // CHR-ROM は書き換えられない
+  pub fn write_chr(&mut self, bank: usize, bank_size: usize, offset: usize, val: u8) {
+    let len = self.chr_ram.len();
+    if len > 0 {
+      self.chr_ram[(bank * bank_size + offset) % len] = val;
+    }
+  }
+
+  // $6000 ~ $7fff
+  pub fn read_prg_ram(&self, addr: usize) -> u8 {
+    if self.prg_ram.is_empty() {
+      return 0;
+    }
+
+    self.prg_ram[(addr - 0x6000) % self.prg_ram.len()]
+  }
+
+  pub fn write_prg_ram(&mut self, addr: usize, val: u8) {
+    let len = self.prg_ram.len();
+    if len > 0 && self.prg_ram[(addr - 0x6000) % len] != val {
+      self.prg_ram[(addr - 0x6000) % len] = val;
+      self.prg_ram_dirty = true;
+    }
+  }
+}
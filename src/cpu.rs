@@ -1,4 +1,16 @@
-use super::machine;
+use super::bus::Bus;
+
+/// # CPU の種類
+///
+/// | Variant | Description |
+/// | - | - |
+/// | `Ricoh2A03` | NES (ファミコン) の CPU。D フラグは立てられるが 10進モードは無い |
+/// | `Mos6502` | 汎用の NMOS 6502。D フラグが立っていると ADC, SBC が 10進数 (BCD) で計算する |
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Variant {
+  Ricoh2A03,
+  Mos6502,
+}
 
 #[derive(Debug, Copy, Clone)]
 pub struct Cpu {
@@ -10,7 +22,9 @@ pub struct Cpu {
   /// Program counter
   pub pc: u16,
   /// Stack pointer
-  pub sp: u16,
+  ///
+  /// スタックは $0100 ~ $01ff に置かれ、下位8bitだけを保持します
+  pub sp: u8,
   ///  Processer status register
   ///
   /// | Bit | Name | Description |
@@ -31,6 +45,28 @@ pub struct Cpu {
   /// | | | set to 1 - if no borrow is required. The carry flag is also used in shift and rotate logical operations.
   ///
   pub p: u8,
+  /// CPU の種類 (NES では `Variant::Ricoh2A03`)
+  pub variant: Variant,
+  /// 電源投入からの累計サイクル数
+  pub cycles: u64,
+  /// 直前のアドレッシングでページ ($xx00 ~ $xxff) を跨いだか
+  pub page_crossed: bool,
+  /// 実行中の命令で基本サイクル数に加算されるサイクル数 (分岐)
+  pub extra_cycles: u8,
+  /// サイクル単位で実行するモード
+  ///
+  /// 有効にすると1回のバスアクセスごとに `Machine::tick` で1サイクル進め、
+  /// 実機と同じ空読み・空書き (RMW 命令の2回書き込みなど) も行います
+  pub cycle_step: bool,
+  /// 割り込みのポーリング状態
+  ///
+  /// NMI は信号の立ち上がり (エッジ), IRQ は信号のレベルで検出します。
+  /// 命令の最後から2番目のサイクルまでに検出したものが命令の後に処理されます
+  pub nmi_line: bool,
+  pub need_nmi: bool,
+  pub prev_need_nmi: bool,
+  pub run_irq: bool,
+  pub prev_run_irq: bool,
 }
 
 impl Cpu {
@@ -42,16 +78,73 @@ impl Cpu {
       pc: 0,
       sp: 0xfe,
       p: 0x20,
+      variant: Variant::Ricoh2A03,
+      cycles: 0,
+      page_crossed: false,
+      extra_cycles: 0,
+      cycle_step: false,
+      nmi_line: false,
+      need_nmi: false,
+      prev_need_nmi: false,
+      run_irq: false,
+      prev_run_irq: false,
+    }
+  }
+
+  // バスアクセス (1回 = 1サイクル)
+  // サイクル単位で実行するときはサイクルの終わりに割り込みをポーリングする
+  pub fn read<B: Bus>(&mut self, bus: &mut B, addr: usize) -> u8 {
+    if self.cycle_step {
+      bus.tick();
+    }
+    let val = bus.read(addr);
+    if self.cycle_step {
+      self.poll_interrupt(bus);
+    }
+    val
+  }
+
+  pub fn write<B: Bus>(&mut self, bus: &mut B, addr: usize, val: u8) {
+    if self.cycle_step {
+      bus.tick();
+    }
+    bus.write(addr, val);
+    if self.cycle_step {
+      self.poll_interrupt(bus);
+    }
+  }
+
+  // 結果を使わない空読み (サイクル単位で実行するときだけ行う)
+  pub fn dummy_read<B: Bus>(&mut self, bus: &mut B, addr: usize) {
+    if self.cycle_step {
+      self.read(bus, addr);
     }
   }
 
-  pub fn push_stack(&mut self, machine: &mut machine::Machine, val: u8) {
-    machine.write(self.sp as usize, val);
-    self.sp -= 1;
+  // 空書き (MMC1 などは書き込みに反応するので常に行う)
+  pub fn dummy_write<B: Bus>(&mut self, bus: &mut B, addr: usize, val: u8) {
+    self.write(bus, addr, val);
+  }
+
+  pub fn push_stack<B: Bus>(&mut self, bus: &mut B, val: u8) {
+    self.write(bus, 0x100 | self.sp as usize, val);
+    self.sp = self.sp.wrapping_sub(1);
+  }
+
+  pub fn pop_stack<B: Bus>(&mut self, bus: &mut B) -> u8 {
+    self.sp = self.sp.wrapping_add(1);
+    self.read(bus, 0x100 | self.sp as usize)
+  }
+
+  // 上位バイトから積むので、メモリ上では下位バイトが低いアドレスに並ぶ
+  pub fn push_stack_16<B: Bus>(&mut self, bus: &mut B, val: u16) {
+    self.push_stack(bus, (val >> 8) as u8);
+    self.push_stack(bus, (val & 0xff) as u8);
   }
 
-  pub fn pop_stack(&mut self, machine: &mut machine::Machine) -> u8 {
-    self.sp += 1;
-    machine.read(self.sp as usize)
+  pub fn pop_stack_16<B: Bus>(&mut self, bus: &mut B) -> u16 {
+    let low = self.pop_stack(bus) as u16;
+    let high = self.pop_stack(bus) as u16;
+    (high << 8) | low
   }
 }
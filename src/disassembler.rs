@@ -0,0 +1,59 @@
+use super::bus::Bus;
+use super::instruction::{self, Addressing};
+
+/// # 逆アセンブラ
+///
+/// `bytes` の先頭にある命令を, `pc` に置かれているものとして文字列にします。
+/// 16進数は小文字で, 非公式の命令には `*` が付きます
+///
+/// | Addressing | Format |
+/// | - | - |
+/// | Implied | `CLC` |
+/// | Accumulator | `ASL A` |
+/// | Immediate | `LDA #$01` |
+/// | Zeropage | `LDA $01` |
+/// | ZeropageX, ZeropageY | `LDA $01,X` |
+/// | Relative | `BNE $c010` (分岐先のアドレス) |
+/// | Absolute | `JMP $c5f5` |
+/// | AbsoluteX, AbsoluteY | `LDA $0200,X` |
+/// | Indirect | `JMP ($0200)` |
+/// | IndirectX | `LDA ($01,X)` |
+/// | IndirectY | `LDA ($01),Y` |
+///
+/// 足りないオペランドは 0 として扱います
+pub fn disassemble(bytes: &[u8], pc: u16) -> String {
+  let info = instruction::decode(bytes.first().copied().unwrap_or(0));
+  let low = bytes.get(1).copied().unwrap_or(0);
+  let high = bytes.get(2).copied().unwrap_or(0);
+  let addr = ((high as u16) << 8) | low as u16;
+  let target = pc.wrapping_add(2).wrapping_add(low as i8 as u16); // 分岐先
+
+  let operand = match info.mode {
+    Addressing::Implied => String::new(),
+    Addressing::Accumulator => " A".to_string(),
+    Addressing::Immediate => format!(" #${:02x}", low),
+    Addressing::Zeropage => format!(" ${:02x}", low),
+    Addressing::ZeropageX => format!(" ${:02x},X", low),
+    Addressing::ZeropageY => format!(" ${:02x},Y", low),
+    Addressing::Relative => format!(" ${:04x}", target),
+    Addressing::Absolute => format!(" ${:04x}", addr),
+    Addressing::AbsoluteX => format!(" ${:04x},X", addr),
+    Addressing::AbsoluteY => format!(" ${:04x},Y", addr),
+    Addressing::Indirect => format!(" (${:04x})", addr),
+    Addressing::IndirectX => format!(" (${:02x},X)", low),
+    Addressing::IndirectY => format!(" (${:02x}),Y", low),
+  };
+
+  format!(
+    "{}{:?}{}",
+    if info.official { "" } else { "*" },
+    info.opcode,
+    operand
+  )
+}
+
+// バスから `pc` の命令を状態を変えずに読んで逆アセンブルする (デバッガ用)
+pub fn disassemble_at<B: Bus>(bus: &B, pc: u16) -> String {
+  let bytes: Vec<u8> = (0..3).map(|i| bus.peek(pc.wrapping_add(i) as usize)).collect();
+  disassemble(&bytes, pc)
+}
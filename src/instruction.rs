@@ -1,10 +1,11 @@
+use super::bus::Bus;
 use super::cpu::*;
-use super::machine;
 
 const ON: bool = true;
 const OFF: bool = false;
 
-#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Opcode {
   // transfer
   LDA,
@@ -84,6 +85,15 @@ pub enum Opcode {
   SAX,
   SKB,
   IGN,
+  // unofficial (unstable)
+  XAA,
+  AHX,
+  SHY,
+  SHX,
+  TAS,
+  LAS,
+  // CPU を停止させる
+  KIL,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -103,6 +113,7 @@ pub enum Addressing {
   IndirectY,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub enum Interrupt {
   RESET,
@@ -111,442 +122,442 @@ pub enum Interrupt {
   BRK,
 }
 
-#[derive(Debug)]
-pub struct Instruction(u8, Opcode, Addressing); // 頭はCycle数
+/// # 命令の情報
+///
+/// | Field | Description |
+/// | - | - |
+/// | `opcode` | 命令 |
+/// | `mode` | アドレッシングモード |
+/// | `cycles` | 基本のサイクル数 (ページ跨ぎ, 分岐, DMA で増える) |
+/// | `bytes` | オペランドを含めた命令の長さ |
+/// | `official` | 公式の命令か |
+/// | `page_penalty` | インデックスでページを跨ぐと +1 サイクルになるか |
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OpcodeInfo {
+  pub opcode: Opcode,
+  pub mode: Addressing,
+  pub cycles: u8,
+  pub bytes: u8,
+  pub official: bool,
+  pub page_penalty: bool,
+}
 
-/// # CPUの命令
+const OFFICIAL: bool = true;
+const UNOFFICIAL: bool = false;
+
+const fn op(opcode: Opcode, mode: Addressing, cycles: u8, official: bool) -> OpcodeInfo {
+  let bytes = match mode {
+    Addressing::Implied | Addressing::Accumulator => 1,
+    Addressing::Absolute | Addressing::AbsoluteX | Addressing::AbsoluteY | Addressing::Indirect => 3,
+    _ => 2,
+  };
+
+  // 読み出し命令だけ (ストアと RMW 命令は基本サイクル数に含まれている)
+  let page_penalty = matches!(
+    opcode,
+    Opcode::LDA
+      | Opcode::LDX
+      | Opcode::LDY
+      | Opcode::EOR
+      | Opcode::AND
+      | Opcode::ORA
+      | Opcode::ADC
+      | Opcode::SBC
+      | Opcode::CMP
+      | Opcode::LAX
+      | Opcode::LAS
+      | Opcode::IGN
+  ) && matches!(
+    mode,
+    Addressing::AbsoluteX | Addressing::AbsoluteY | Addressing::IndirectY
+  );
+
+  OpcodeInfo {
+    opcode,
+    mode,
+    cycles,
+    bytes,
+    official,
+    page_penalty,
+  }
+}
+
+/// # CPUの命令表
+///
+/// マシンコード ($00 ~ $ff) から命令の情報を引く表です。
+/// CPU の実行, 逆アセンブラ, デバッガで共有します
 ///
-/// マシンコードを命令とアドレッシングモードに変換します
+/// https://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+pub static OPCODES: [OpcodeInfo; 256] = [
+  op(Opcode::BRK, Addressing::Implied, 7, OFFICIAL), // 0x00
+  op(Opcode::ORA, Addressing::IndirectX, 6, OFFICIAL), // 0x01
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x02
+  op(Opcode::SLO, Addressing::IndirectX, 8, UNOFFICIAL), // 0x03
+  op(Opcode::IGN, Addressing::Zeropage, 3, UNOFFICIAL), // 0x04
+  op(Opcode::ORA, Addressing::Zeropage, 3, OFFICIAL), // 0x05
+  op(Opcode::ASL, Addressing::Zeropage, 5, OFFICIAL), // 0x06
+  op(Opcode::SLO, Addressing::Zeropage, 5, UNOFFICIAL), // 0x07
+  op(Opcode::PHP, Addressing::Implied, 3, OFFICIAL), // 0x08
+  op(Opcode::ORA, Addressing::Immediate, 2, OFFICIAL), // 0x09
+  op(Opcode::ASL, Addressing::Accumulator, 2, OFFICIAL), // 0x0a
+  op(Opcode::ANC, Addressing::Immediate, 2, UNOFFICIAL), // 0x0b
+  op(Opcode::IGN, Addressing::Absolute, 4, UNOFFICIAL), // 0x0c
+  op(Opcode::ORA, Addressing::Absolute, 4, OFFICIAL), // 0x0d
+  op(Opcode::ASL, Addressing::Absolute, 6, OFFICIAL), // 0x0e
+  op(Opcode::SLO, Addressing::Absolute, 6, UNOFFICIAL), // 0x0f
+  op(Opcode::BPL, Addressing::Relative, 2, OFFICIAL), // 0x10
+  op(Opcode::ORA, Addressing::IndirectY, 5, OFFICIAL), // 0x11
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x12
+  op(Opcode::SLO, Addressing::IndirectY, 8, UNOFFICIAL), // 0x13
+  op(Opcode::IGN, Addressing::ZeropageX, 4, UNOFFICIAL), // 0x14
+  op(Opcode::ORA, Addressing::ZeropageX, 4, OFFICIAL), // 0x15
+  op(Opcode::ASL, Addressing::ZeropageX, 6, OFFICIAL), // 0x16
+  op(Opcode::SLO, Addressing::ZeropageX, 6, UNOFFICIAL), // 0x17
+  op(Opcode::CLC, Addressing::Implied, 2, OFFICIAL), // 0x18
+  op(Opcode::ORA, Addressing::AbsoluteY, 4, OFFICIAL), // 0x19
+  op(Opcode::NOP, Addressing::Implied, 2, UNOFFICIAL), // 0x1a
+  op(Opcode::SLO, Addressing::AbsoluteY, 7, UNOFFICIAL), // 0x1b
+  op(Opcode::IGN, Addressing::AbsoluteX, 4, UNOFFICIAL), // 0x1c
+  op(Opcode::ORA, Addressing::AbsoluteX, 4, OFFICIAL), // 0x1d
+  op(Opcode::ASL, Addressing::AbsoluteX, 7, OFFICIAL), // 0x1e
+  op(Opcode::SLO, Addressing::AbsoluteX, 7, UNOFFICIAL), // 0x1f
+  op(Opcode::JSR, Addressing::Absolute, 6, OFFICIAL), // 0x20
+  op(Opcode::AND, Addressing::IndirectX, 6, OFFICIAL), // 0x21
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x22
+  op(Opcode::RLA, Addressing::IndirectX, 8, UNOFFICIAL), // 0x23
+  op(Opcode::BIT, Addressing::Zeropage, 3, OFFICIAL), // 0x24
+  op(Opcode::AND, Addressing::Zeropage, 3, OFFICIAL), // 0x25
+  op(Opcode::ROL, Addressing::Zeropage, 5, OFFICIAL), // 0x26
+  op(Opcode::RLA, Addressing::Zeropage, 5, UNOFFICIAL), // 0x27
+  op(Opcode::PLP, Addressing::Implied, 4, OFFICIAL), // 0x28
+  op(Opcode::AND, Addressing::Immediate, 2, OFFICIAL), // 0x29
+  op(Opcode::ROL, Addressing::Accumulator, 2, OFFICIAL), // 0x2a
+  op(Opcode::ANC, Addressing::Immediate, 2, UNOFFICIAL), // 0x2b
+  op(Opcode::BIT, Addressing::Absolute, 4, OFFICIAL), // 0x2c
+  op(Opcode::AND, Addressing::Absolute, 4, OFFICIAL), // 0x2d
+  op(Opcode::ROL, Addressing::Absolute, 6, OFFICIAL), // 0x2e
+  op(Opcode::RLA, Addressing::Absolute, 6, UNOFFICIAL), // 0x2f
+  op(Opcode::BMI, Addressing::Relative, 2, OFFICIAL), // 0x30
+  op(Opcode::AND, Addressing::IndirectY, 5, OFFICIAL), // 0x31
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x32
+  op(Opcode::RLA, Addressing::IndirectY, 8, UNOFFICIAL), // 0x33
+  op(Opcode::IGN, Addressing::ZeropageX, 4, UNOFFICIAL), // 0x34
+  op(Opcode::AND, Addressing::ZeropageX, 4, OFFICIAL), // 0x35
+  op(Opcode::ROL, Addressing::ZeropageX, 6, OFFICIAL), // 0x36
+  op(Opcode::RLA, Addressing::ZeropageX, 6, UNOFFICIAL), // 0x37
+  op(Opcode::SEC, Addressing::Implied, 2, OFFICIAL), // 0x38
+  op(Opcode::AND, Addressing::AbsoluteY, 4, OFFICIAL), // 0x39
+  op(Opcode::NOP, Addressing::Implied, 2, UNOFFICIAL), // 0x3a
+  op(Opcode::RLA, Addressing::AbsoluteY, 7, UNOFFICIAL), // 0x3b
+  op(Opcode::IGN, Addressing::AbsoluteX, 4, UNOFFICIAL), // 0x3c
+  op(Opcode::AND, Addressing::AbsoluteX, 4, OFFICIAL), // 0x3d
+  op(Opcode::ROL, Addressing::AbsoluteX, 7, OFFICIAL), // 0x3e
+  op(Opcode::RLA, Addressing::AbsoluteX, 7, UNOFFICIAL), // 0x3f
+  op(Opcode::RTI, Addressing::Implied, 6, OFFICIAL), // 0x40
+  op(Opcode::EOR, Addressing::IndirectX, 6, OFFICIAL), // 0x41
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x42
+  op(Opcode::SRE, Addressing::IndirectX, 8, UNOFFICIAL), // 0x43
+  op(Opcode::IGN, Addressing::Zeropage, 3, UNOFFICIAL), // 0x44
+  op(Opcode::EOR, Addressing::Zeropage, 3, OFFICIAL), // 0x45
+  op(Opcode::LSR, Addressing::Zeropage, 5, OFFICIAL), // 0x46
+  op(Opcode::SRE, Addressing::Zeropage, 5, UNOFFICIAL), // 0x47
+  op(Opcode::PHA, Addressing::Implied, 3, OFFICIAL), // 0x48
+  op(Opcode::EOR, Addressing::Immediate, 2, OFFICIAL), // 0x49
+  op(Opcode::LSR, Addressing::Accumulator, 2, OFFICIAL), // 0x4a
+  op(Opcode::ALR, Addressing::Immediate, 2, UNOFFICIAL), // 0x4b
+  op(Opcode::JMP, Addressing::Absolute, 3, OFFICIAL), // 0x4c
+  op(Opcode::EOR, Addressing::Absolute, 4, OFFICIAL), // 0x4d
+  op(Opcode::LSR, Addressing::Absolute, 6, OFFICIAL), // 0x4e
+  op(Opcode::SRE, Addressing::Absolute, 6, UNOFFICIAL), // 0x4f
+  op(Opcode::BVC, Addressing::Relative, 2, OFFICIAL), // 0x50
+  op(Opcode::EOR, Addressing::IndirectY, 5, OFFICIAL), // 0x51
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x52
+  op(Opcode::SRE, Addressing::IndirectY, 8, UNOFFICIAL), // 0x53
+  op(Opcode::IGN, Addressing::ZeropageX, 4, UNOFFICIAL), // 0x54
+  op(Opcode::EOR, Addressing::ZeropageX, 4, OFFICIAL), // 0x55
+  op(Opcode::LSR, Addressing::ZeropageX, 6, OFFICIAL), // 0x56
+  op(Opcode::SRE, Addressing::ZeropageX, 6, UNOFFICIAL), // 0x57
+  op(Opcode::CLI, Addressing::Implied, 2, OFFICIAL), // 0x58
+  op(Opcode::EOR, Addressing::AbsoluteY, 4, OFFICIAL), // 0x59
+  op(Opcode::NOP, Addressing::Implied, 2, UNOFFICIAL), // 0x5a
+  op(Opcode::SRE, Addressing::AbsoluteY, 7, UNOFFICIAL), // 0x5b
+  op(Opcode::IGN, Addressing::AbsoluteX, 4, UNOFFICIAL), // 0x5c
+  op(Opcode::EOR, Addressing::AbsoluteX, 4, OFFICIAL), // 0x5d
+  op(Opcode::LSR, Addressing::AbsoluteX, 7, OFFICIAL), // 0x5e
+  op(Opcode::SRE, Addressing::AbsoluteX, 7, UNOFFICIAL), // 0x5f
+  op(Opcode::RTS, Addressing::Implied, 6, OFFICIAL), // 0x60
+  op(Opcode::ADC, Addressing::IndirectX, 6, OFFICIAL), // 0x61
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x62
+  op(Opcode::RRA, Addressing::IndirectX, 8, UNOFFICIAL), // 0x63
+  op(Opcode::IGN, Addressing::Zeropage, 3, UNOFFICIAL), // 0x64
+  op(Opcode::ADC, Addressing::Zeropage, 3, OFFICIAL), // 0x65
+  op(Opcode::ROR, Addressing::Zeropage, 5, OFFICIAL), // 0x66
+  op(Opcode::RRA, Addressing::Zeropage, 5, UNOFFICIAL), // 0x67
+  op(Opcode::PLA, Addressing::Implied, 4, OFFICIAL), // 0x68
+  op(Opcode::ADC, Addressing::Immediate, 2, OFFICIAL), // 0x69
+  op(Opcode::ROR, Addressing::Accumulator, 2, OFFICIAL), // 0x6a
+  op(Opcode::ARR, Addressing::Immediate, 2, UNOFFICIAL), // 0x6b
+  op(Opcode::JMP, Addressing::Indirect, 5, OFFICIAL), // 0x6c
+  op(Opcode::ADC, Addressing::Absolute, 4, OFFICIAL), // 0x6d
+  op(Opcode::ROR, Addressing::Absolute, 6, OFFICIAL), // 0x6e
+  op(Opcode::RRA, Addressing::Absolute, 6, UNOFFICIAL), // 0x6f
+  op(Opcode::BVS, Addressing::Relative, 2, OFFICIAL), // 0x70
+  op(Opcode::ADC, Addressing::IndirectY, 5, OFFICIAL), // 0x71
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x72
+  op(Opcode::RRA, Addressing::IndirectY, 8, UNOFFICIAL), // 0x73
+  op(Opcode::IGN, Addressing::ZeropageX, 4, UNOFFICIAL), // 0x74
+  op(Opcode::ADC, Addressing::ZeropageX, 4, OFFICIAL), // 0x75
+  op(Opcode::ROR, Addressing::ZeropageX, 6, OFFICIAL), // 0x76
+  op(Opcode::RRA, Addressing::ZeropageX, 6, UNOFFICIAL), // 0x77
+  op(Opcode::SEI, Addressing::Implied, 2, OFFICIAL), // 0x78
+  op(Opcode::ADC, Addressing::AbsoluteY, 4, OFFICIAL), // 0x79
+  op(Opcode::NOP, Addressing::Implied, 2, UNOFFICIAL), // 0x7a
+  op(Opcode::RRA, Addressing::AbsoluteY, 7, UNOFFICIAL), // 0x7b
+  op(Opcode::IGN, Addressing::AbsoluteX, 4, UNOFFICIAL), // 0x7c
+  op(Opcode::ADC, Addressing::AbsoluteX, 4, OFFICIAL), // 0x7d
+  op(Opcode::ROR, Addressing::AbsoluteX, 7, OFFICIAL), // 0x7e
+  op(Opcode::RRA, Addressing::AbsoluteX, 7, UNOFFICIAL), // 0x7f
+  op(Opcode::SKB, Addressing::Immediate, 2, UNOFFICIAL), // 0x80
+  op(Opcode::STA, Addressing::IndirectX, 6, OFFICIAL), // 0x81
+  op(Opcode::SKB, Addressing::Immediate, 2, UNOFFICIAL), // 0x82
+  op(Opcode::SAX, Addressing::IndirectX, 6, UNOFFICIAL), // 0x83
+  op(Opcode::STY, Addressing::Zeropage, 3, OFFICIAL), // 0x84
+  op(Opcode::STA, Addressing::Zeropage, 3, OFFICIAL), // 0x85
+  op(Opcode::STX, Addressing::Zeropage, 3, OFFICIAL), // 0x86
+  op(Opcode::SAX, Addressing::Zeropage, 3, UNOFFICIAL), // 0x87
+  op(Opcode::DEY, Addressing::Implied, 2, OFFICIAL), // 0x88
+  op(Opcode::SKB, Addressing::Immediate, 2, UNOFFICIAL), // 0x89
+  op(Opcode::TXA, Addressing::Implied, 2, OFFICIAL), // 0x8a
+  op(Opcode::XAA, Addressing::Immediate, 2, UNOFFICIAL), // 0x8b
+  op(Opcode::STY, Addressing::Absolute, 4, OFFICIAL), // 0x8c
+  op(Opcode::STA, Addressing::Absolute, 4, OFFICIAL), // 0x8d
+  op(Opcode::STX, Addressing::Absolute, 4, OFFICIAL), // 0x8e
+  op(Opcode::SAX, Addressing::Absolute, 4, UNOFFICIAL), // 0x8f
+  op(Opcode::BCC, Addressing::Relative, 2, OFFICIAL), // 0x90
+  op(Opcode::STA, Addressing::IndirectY, 6, OFFICIAL), // 0x91
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0x92
+  op(Opcode::AHX, Addressing::IndirectY, 6, UNOFFICIAL), // 0x93
+  op(Opcode::STY, Addressing::ZeropageX, 4, OFFICIAL), // 0x94
+  op(Opcode::STA, Addressing::ZeropageX, 4, OFFICIAL), // 0x95
+  op(Opcode::STX, Addressing::ZeropageY, 4, OFFICIAL), // 0x96
+  op(Opcode::SAX, Addressing::ZeropageY, 4, UNOFFICIAL), // 0x97
+  op(Opcode::TYA, Addressing::Implied, 2, OFFICIAL), // 0x98
+  op(Opcode::STA, Addressing::AbsoluteY, 5, OFFICIAL), // 0x99
+  op(Opcode::TXS, Addressing::Implied, 2, OFFICIAL), // 0x9a
+  op(Opcode::TAS, Addressing::AbsoluteY, 5, UNOFFICIAL), // 0x9b
+  op(Opcode::SHY, Addressing::AbsoluteX, 5, UNOFFICIAL), // 0x9c
+  op(Opcode::STA, Addressing::AbsoluteX, 5, OFFICIAL), // 0x9d
+  op(Opcode::SHX, Addressing::AbsoluteY, 5, UNOFFICIAL), // 0x9e
+  op(Opcode::AHX, Addressing::AbsoluteY, 5, UNOFFICIAL), // 0x9f
+  op(Opcode::LDY, Addressing::Immediate, 2, OFFICIAL), // 0xa0
+  op(Opcode::LDA, Addressing::IndirectX, 6, OFFICIAL), // 0xa1
+  op(Opcode::LDX, Addressing::Immediate, 2, OFFICIAL), // 0xa2
+  op(Opcode::LAX, Addressing::IndirectX, 6, UNOFFICIAL), // 0xa3
+  op(Opcode::LDY, Addressing::Zeropage, 3, OFFICIAL), // 0xa4
+  op(Opcode::LDA, Addressing::Zeropage, 3, OFFICIAL), // 0xa5
+  op(Opcode::LDX, Addressing::Zeropage, 3, OFFICIAL), // 0xa6
+  op(Opcode::LAX, Addressing::Zeropage, 3, UNOFFICIAL), // 0xa7
+  op(Opcode::TAY, Addressing::Implied, 2, OFFICIAL), // 0xa8
+  op(Opcode::LDA, Addressing::Immediate, 2, OFFICIAL), // 0xa9
+  op(Opcode::TAX, Addressing::Implied, 2, OFFICIAL), // 0xaa
+  op(Opcode::LAX, Addressing::Immediate, 2, UNOFFICIAL), // 0xab
+  op(Opcode::LDY, Addressing::Absolute, 4, OFFICIAL), // 0xac
+  op(Opcode::LDA, Addressing::Absolute, 4, OFFICIAL), // 0xad
+  op(Opcode::LDX, Addressing::Absolute, 4, OFFICIAL), // 0xae
+  op(Opcode::LAX, Addressing::Absolute, 4, UNOFFICIAL), // 0xaf
+  op(Opcode::BCS, Addressing::Relative, 2, OFFICIAL), // 0xb0
+  op(Opcode::LDA, Addressing::IndirectY, 5, OFFICIAL), // 0xb1
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0xb2
+  op(Opcode::LAX, Addressing::IndirectY, 5, UNOFFICIAL), // 0xb3
+  op(Opcode::LDY, Addressing::ZeropageX, 4, OFFICIAL), // 0xb4
+  op(Opcode::LDA, Addressing::ZeropageX, 4, OFFICIAL), // 0xb5
+  op(Opcode::LDX, Addressing::ZeropageY, 4, OFFICIAL), // 0xb6
+  op(Opcode::LAX, Addressing::ZeropageY, 4, UNOFFICIAL), // 0xb7
+  op(Opcode::CLV, Addressing::Implied, 2, OFFICIAL), // 0xb8
+  op(Opcode::LDA, Addressing::AbsoluteY, 4, OFFICIAL), // 0xb9
+  op(Opcode::TSX, Addressing::Implied, 2, OFFICIAL), // 0xba
+  op(Opcode::LAS, Addressing::AbsoluteY, 4, UNOFFICIAL), // 0xbb
+  op(Opcode::LDY, Addressing::AbsoluteX, 4, OFFICIAL), // 0xbc
+  op(Opcode::LDA, Addressing::AbsoluteX, 4, OFFICIAL), // 0xbd
+  op(Opcode::LDX, Addressing::AbsoluteY, 4, OFFICIAL), // 0xbe
+  op(Opcode::LAX, Addressing::AbsoluteY, 4, UNOFFICIAL), // 0xbf
+  op(Opcode::CPY, Addressing::Immediate, 2, OFFICIAL), // 0xc0
+  op(Opcode::CMP, Addressing::IndirectX, 6, OFFICIAL), // 0xc1
+  op(Opcode::SKB, Addressing::Immediate, 2, UNOFFICIAL), // 0xc2
+  op(Opcode::DCP, Addressing::IndirectX, 8, UNOFFICIAL), // 0xc3
+  op(Opcode::CPY, Addressing::Zeropage, 3, OFFICIAL), // 0xc4
+  op(Opcode::CMP, Addressing::Zeropage, 3, OFFICIAL), // 0xc5
+  op(Opcode::DEC, Addressing::Zeropage, 5, OFFICIAL), // 0xc6
+  op(Opcode::DCP, Addressing::Zeropage, 5, UNOFFICIAL), // 0xc7
+  op(Opcode::INY, Addressing::Implied, 2, OFFICIAL), // 0xc8
+  op(Opcode::CMP, Addressing::Immediate, 2, OFFICIAL), // 0xc9
+  op(Opcode::DEX, Addressing::Implied, 2, OFFICIAL), // 0xca
+  op(Opcode::AXS, Addressing::Immediate, 2, UNOFFICIAL), // 0xcb
+  op(Opcode::CPY, Addressing::Absolute, 4, OFFICIAL), // 0xcc
+  op(Opcode::CMP, Addressing::Absolute, 4, OFFICIAL), // 0xcd
+  op(Opcode::DEC, Addressing::Absolute, 6, OFFICIAL), // 0xce
+  op(Opcode::DCP, Addressing::Absolute, 6, UNOFFICIAL), // 0xcf
+  op(Opcode::BNE, Addressing::Relative, 2, OFFICIAL), // 0xd0
+  op(Opcode::CMP, Addressing::IndirectY, 5, OFFICIAL), // 0xd1
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0xd2
+  op(Opcode::DCP, Addressing::IndirectY, 8, UNOFFICIAL), // 0xd3
+  op(Opcode::IGN, Addressing::ZeropageX, 4, UNOFFICIAL), // 0xd4
+  op(Opcode::CMP, Addressing::ZeropageX, 4, OFFICIAL), // 0xd5
+  op(Opcode::DEC, Addressing::ZeropageX, 6, OFFICIAL), // 0xd6
+  op(Opcode::DCP, Addressing::ZeropageX, 6, UNOFFICIAL), // 0xd7
+  op(Opcode::CLD, Addressing::Implied, 2, OFFICIAL), // 0xd8
+  op(Opcode::CMP, Addressing::AbsoluteY, 4, OFFICIAL), // 0xd9
+  op(Opcode::NOP, Addressing::Implied, 2, UNOFFICIAL), // 0xda
+  op(Opcode::DCP, Addressing::AbsoluteY, 7, UNOFFICIAL), // 0xdb
+  op(Opcode::IGN, Addressing::AbsoluteX, 4, UNOFFICIAL), // 0xdc
+  op(Opcode::CMP, Addressing::AbsoluteX, 4, OFFICIAL), // 0xdd
+  op(Opcode::DEC, Addressing::AbsoluteX, 7, OFFICIAL), // 0xde
+  op(Opcode::DCP, Addressing::AbsoluteX, 7, UNOFFICIAL), // 0xdf
+  op(Opcode::CPX, Addressing::Immediate, 2, OFFICIAL), // 0xe0
+  op(Opcode::SBC, Addressing::IndirectX, 6, OFFICIAL), // 0xe1
+  op(Opcode::SKB, Addressing::Immediate, 2, UNOFFICIAL), // 0xe2
+  op(Opcode::ISC, Addressing::IndirectX, 8, UNOFFICIAL), // 0xe3
+  op(Opcode::CPX, Addressing::Zeropage, 3, OFFICIAL), // 0xe4
+  op(Opcode::SBC, Addressing::Zeropage, 3, OFFICIAL), // 0xe5
+  op(Opcode::INC, Addressing::Zeropage, 5, OFFICIAL), // 0xe6
+  op(Opcode::ISC, Addressing::Zeropage, 5, UNOFFICIAL), // 0xe7
+  op(Opcode::INX, Addressing::Implied, 2, OFFICIAL), // 0xe8
+  op(Opcode::SBC, Addressing::Immediate, 2, OFFICIAL), // 0xe9
+  op(Opcode::NOP, Addressing::Implied, 2, OFFICIAL), // 0xea
+  op(Opcode::SBC, Addressing::Immediate, 2, UNOFFICIAL), // 0xeb
+  op(Opcode::CPX, Addressing::Absolute, 4, OFFICIAL), // 0xec
+  op(Opcode::SBC, Addressing::Absolute, 4, OFFICIAL), // 0xed
+  op(Opcode::INC, Addressing::Absolute, 6, OFFICIAL), // 0xee
+  op(Opcode::ISC, Addressing::Absolute, 6, UNOFFICIAL), // 0xef
+  op(Opcode::BEQ, Addressing::Relative, 2, OFFICIAL), // 0xf0
+  op(Opcode::SBC, Addressing::IndirectY, 5, OFFICIAL), // 0xf1
+  op(Opcode::KIL, Addressing::Implied, 2, UNOFFICIAL), // 0xf2
+  op(Opcode::ISC, Addressing::IndirectY, 8, UNOFFICIAL), // 0xf3
+  op(Opcode::IGN, Addressing::ZeropageX, 4, UNOFFICIAL), // 0xf4
+  op(Opcode::SBC, Addressing::ZeropageX, 4, OFFICIAL), // 0xf5
+  op(Opcode::INC, Addressing::ZeropageX, 6, OFFICIAL), // 0xf6
+  op(Opcode::ISC, Addressing::ZeropageX, 6, UNOFFICIAL), // 0xf7
+  op(Opcode::SED, Addressing::Implied, 2, OFFICIAL), // 0xf8
+  op(Opcode::SBC, Addressing::AbsoluteY, 4, OFFICIAL), // 0xf9
+  op(Opcode::NOP, Addressing::Implied, 2, UNOFFICIAL), // 0xfa
+  op(Opcode::ISC, Addressing::AbsoluteY, 7, UNOFFICIAL), // 0xfb
+  op(Opcode::IGN, Addressing::AbsoluteX, 4, UNOFFICIAL), // 0xfc
+  op(Opcode::SBC, Addressing::AbsoluteX, 4, OFFICIAL), // 0xfd
+  op(Opcode::INC, Addressing::AbsoluteX, 7, OFFICIAL), // 0xfe
+  op(Opcode::ISC, Addressing::AbsoluteX, 7, UNOFFICIAL), // 0xff
+];
+
+/// マシンコードを命令に変換します
 ///
-/// ```rust
-/// fn main() {
-///   // Create CPU
-///   let cpu = cpu::Cpu::new();
-///   // Return (LDA, Immediate)
-///   let inst = cpu.convert(0xa9);
-/// }
+/// ```ignore
+/// // Return (LDA, Immediate)
+/// let info = instruction::decode(0xa9);
 /// ```
-impl Cpu {
-  // TODO: ROMのマシンコードを命令に変換する
-  pub fn convert(self, code: u8) -> Instruction {
-    match code {
-      /// 転送命令
-      // LDA
-      0xa9 => Instruction(2, Opcode::LDA, Addressing::Immediate),
-      0xa5 => Instruction(3, Opcode::LDA, Addressing::Zeropage),
-      0xb5 => Instruction(4, Opcode::LDA, Addressing::ZeropageX),
-      0xad => Instruction(4, Opcode::LDA, Addressing::Absolute),
-      0xbd => Instruction(4, Opcode::LDA, Addressing::AbsoluteX),
-      0xb9 => Instruction(4, Opcode::LDA, Addressing::AbsoluteY),
-      0xa1 => Instruction(5, Opcode::LDA, Addressing::IndirectX),
-      0xb1 => Instruction(6, Opcode::LDA, Addressing::IndirectY),
-      // LDX
-      0xa2 => Instruction(2, Opcode::LDX, Addressing::Immediate),
-      0xa6 => Instruction(3, Opcode::LDX, Addressing::Zeropage),
-      0xb6 => Instruction(4, Opcode::LDX, Addressing::ZeropageY),
-      0xae => Instruction(4, Opcode::LDX, Addressing::Absolute),
-      0xbe => Instruction(4, Opcode::LDX, Addressing::AbsoluteY),
-      // LDY
-      0xa0 => Instruction(2, Opcode::LDY, Addressing::Immediate),
-      0xa4 => Instruction(3, Opcode::LDY, Addressing::Zeropage),
-      0xb4 => Instruction(4, Opcode::LDY, Addressing::ZeropageX),
-      0xac => Instruction(4, Opcode::LDY, Addressing::Absolute),
-      0xbc => Instruction(4, Opcode::LDY, Addressing::AbsoluteX),
-      // STA
-      0x85 => Instruction(3, Opcode::STA, Addressing::Zeropage),
-      0x95 => Instruction(4, Opcode::STA, Addressing::ZeropageX),
-      0x8d => Instruction(4, Opcode::STA, Addressing::Absolute),
-      0x9d => Instruction(5, Opcode::STA, Addressing::AbsoluteX),
-      0x99 => Instruction(5, Opcode::STA, Addressing::AbsoluteY),
-      0x81 => Instruction(6, Opcode::STA, Addressing::IndirectX),
-      0x91 => Instruction(6, Opcode::STA, Addressing::IndirectY),
-      // STX
-      0x86 => Instruction(3, Opcode::STX, Addressing::Zeropage),
-      0x96 => Instruction(4, Opcode::STX, Addressing::ZeropageY),
-      0x8e => Instruction(4, Opcode::STX, Addressing::Absolute),
-      // STY
-      0x84 => Instruction(3, Opcode::STY, Addressing::Zeropage),
-      0x94 => Instruction(4, Opcode::STY, Addressing::ZeropageX),
-      0x8c => Instruction(4, Opcode::STY, Addressing::Absolute),
-      // TAX, TAY, TSX, TXA, TXS, TYA
-      0xaa => Instruction(2, Opcode::TAX, Addressing::Implied),
-      0xa8 => Instruction(2, Opcode::TAY, Addressing::Implied),
-      0xba => Instruction(2, Opcode::TSX, Addressing::Implied),
-      0x8a => Instruction(2, Opcode::TXA, Addressing::Implied),
-      0x9a => Instruction(2, Opcode::TXS, Addressing::Implied),
-      0x98 => Instruction(2, Opcode::TYA, Addressing::Implied),
-
-      /// 算術命令
-      // ADC
-      0x69 => Instruction(2, Opcode::ADC, Addressing::Immediate),
-      0x65 => Instruction(3, Opcode::ADC, Addressing::Zeropage),
-      0x75 => Instruction(4, Opcode::ADC, Addressing::ZeropageX),
-      0x6d => Instruction(4, Opcode::ADC, Addressing::Absolute),
-      0x7d => Instruction(4, Opcode::ADC, Addressing::AbsoluteX),
-      0x79 => Instruction(4, Opcode::ADC, Addressing::AbsoluteY),
-      0x61 => Instruction(6, Opcode::ADC, Addressing::IndirectX),
-      0x71 => Instruction(5, Opcode::ADC, Addressing::IndirectY),
-      // AND
-      0x29 => Instruction(2, Opcode::AND, Addressing::Immediate),
-      0x25 => Instruction(3, Opcode::AND, Addressing::Zeropage),
-      0x35 => Instruction(4, Opcode::AND, Addressing::ZeropageX),
-      0x2d => Instruction(4, Opcode::AND, Addressing::Absolute),
-      0x3d => Instruction(4, Opcode::AND, Addressing::AbsoluteX),
-      0x39 => Instruction(4, Opcode::AND, Addressing::AbsoluteY),
-      0x21 => Instruction(6, Opcode::AND, Addressing::IndirectX),
-      0x31 => Instruction(5, Opcode::AND, Addressing::IndirectY),
-      // ASL
-      0x0a => Instruction(2, Opcode::ASL, Addressing::Accumulator),
-      0x06 => Instruction(5, Opcode::ASL, Addressing::Zeropage),
-      0x16 => Instruction(6, Opcode::ASL, Addressing::ZeropageX),
-      0x0e => Instruction(6, Opcode::ASL, Addressing::Absolute),
-      0x1e => Instruction(7, Opcode::ASL, Addressing::AbsoluteX),
-      // BIT
-      0x24 => Instruction(3, Opcode::BIT, Addressing::Zeropage),
-      0x2c => Instruction(4, Opcode::BIT, Addressing::Absolute),
-      // CMP
-      0xc9 => Instruction(2, Opcode::CMP, Addressing::Immediate),
-      0xc5 => Instruction(3, Opcode::CMP, Addressing::Zeropage),
-      0xd5 => Instruction(4, Opcode::CMP, Addressing::ZeropageX),
-      0xcd => Instruction(4, Opcode::CMP, Addressing::Absolute),
-      0xdd => Instruction(4, Opcode::CMP, Addressing::AbsoluteX),
-      0xd9 => Instruction(4, Opcode::CMP, Addressing::AbsoluteY),
-      0xc1 => Instruction(6, Opcode::CMP, Addressing::IndirectX),
-      0xd1 => Instruction(5, Opcode::CMP, Addressing::IndirectY),
-      // CPX
-      0xe0 => Instruction(2, Opcode::CPX, Addressing::Immediate),
-      0xe4 => Instruction(3, Opcode::CPX, Addressing::Zeropage),
-      0xec => Instruction(4, Opcode::CPX, Addressing::Absolute),
-      // CPY
-      0xc0 => Instruction(2, Opcode::CPX, Addressing::Immediate),
-      0xc4 => Instruction(3, Opcode::CPX, Addressing::Zeropage),
-      0xcc => Instruction(4, Opcode::CPX, Addressing::Absolute),
-      // DEC
-      0xc6 => Instruction(5, Opcode::DEC, Addressing::Zeropage),
-      0xd6 => Instruction(6, Opcode::DEC, Addressing::ZeropageX),
-      0xce => Instruction(6, Opcode::DEC, Addressing::Absolute),
-      0xde => Instruction(7, Opcode::DEC, Addressing::AbsoluteX),
-      // DEX, DEY
-      0xca => Instruction(2, Opcode::DEX, Addressing::Implied),
-      0x88 => Instruction(2, Opcode::DEY, Addressing::Implied),
-      // EOR
-      0x49 => Instruction(2, Opcode::EOR, Addressing::Immediate),
-      0x45 => Instruction(3, Opcode::EOR, Addressing::Zeropage),
-      0x55 => Instruction(4, Opcode::EOR, Addressing::ZeropageX),
-      0x4d => Instruction(4, Opcode::EOR, Addressing::Absolute),
-      0x5d => Instruction(4, Opcode::EOR, Addressing::AbsoluteX),
-      0x59 => Instruction(4, Opcode::EOR, Addressing::AbsoluteY),
-      0x41 => Instruction(6, Opcode::EOR, Addressing::IndirectX),
-      0x51 => Instruction(5, Opcode::EOR, Addressing::IndirectY),
-      // INC
-      0xe6 => Instruction(5, Opcode::INC, Addressing::Zeropage),
-      0xf6 => Instruction(6, Opcode::INC, Addressing::ZeropageX),
-      0xee => Instruction(6, Opcode::INC, Addressing::Absolute),
-      0xfe => Instruction(7, Opcode::INC, Addressing::AbsoluteX),
-      // INX, INY
-      0xe8 => Instruction(2, Opcode::INX, Addressing::Implied),
-      0xc8 => Instruction(2, Opcode::INY, Addressing::Implied),
-      // LSR
-      0x4a => Instruction(2, Opcode::LSR, Addressing::Accumulator),
-      0x46 => Instruction(5, Opcode::LSR, Addressing::Zeropage),
-      0x56 => Instruction(6, Opcode::LSR, Addressing::ZeropageX),
-      0x4e => Instruction(6, Opcode::LSR, Addressing::Absolute),
-      0x5e => Instruction(7, Opcode::LSR, Addressing::AbsoluteX),
-      // ORA
-      0x09 => Instruction(2, Opcode::ORA, Addressing::Immediate),
-      0x05 => Instruction(3, Opcode::ORA, Addressing::Zeropage),
-      0x15 => Instruction(4, Opcode::ORA, Addressing::ZeropageX),
-      0x0d => Instruction(4, Opcode::ORA, Addressing::Absolute),
-      0x1d => Instruction(4, Opcode::ORA, Addressing::AbsoluteX),
-      0x19 => Instruction(4, Opcode::ORA, Addressing::AbsoluteY),
-      0x01 => Instruction(6, Opcode::ORA, Addressing::IndirectX),
-      0x11 => Instruction(5, Opcode::ORA, Addressing::IndirectY),
-      // ROL
-      0x2a => Instruction(2, Opcode::ROL, Addressing::Accumulator),
-      0x26 => Instruction(5, Opcode::ROL, Addressing::Zeropage),
-      0x36 => Instruction(6, Opcode::ROL, Addressing::ZeropageX),
-      0x2e => Instruction(6, Opcode::ROL, Addressing::Absolute),
-      0x3e => Instruction(7, Opcode::ROL, Addressing::AbsoluteX),
-      // ROR
-      0x6a => Instruction(2, Opcode::ROR, Addressing::Accumulator),
-      0x66 => Instruction(5, Opcode::ROR, Addressing::Zeropage),
-      0x76 => Instruction(6, Opcode::ROR, Addressing::ZeropageX),
-      0x6e => Instruction(6, Opcode::ROR, Addressing::Absolute),
-      0x7e => Instruction(7, Opcode::ROR, Addressing::AbsoluteX),
-      // SBC
-      0xe9 => Instruction(2, Opcode::SBC, Addressing::Immediate),
-      0xe5 => Instruction(3, Opcode::SBC, Addressing::Zeropage),
-      0xf5 => Instruction(4, Opcode::SBC, Addressing::ZeropageX),
-      0xed => Instruction(4, Opcode::SBC, Addressing::Absolute),
-      0xfd => Instruction(4, Opcode::SBC, Addressing::AbsoluteX),
-      0xf9 => Instruction(4, Opcode::SBC, Addressing::AbsoluteY),
-      0xe1 => Instruction(6, Opcode::SBC, Addressing::IndirectX),
-      0xf1 => Instruction(5, Opcode::SBC, Addressing::IndirectY),
-
-      /// スタック命令
-      // PHA, PHP, PLA, PLP
-      0x48 => Instruction(3, Opcode::PHA, Addressing::Implied),
-      0x08 => Instruction(3, Opcode::PHP, Addressing::Implied),
-      0x68 => Instruction(4, Opcode::PLA, Addressing::Implied),
-      0x28 => Instruction(4, Opcode::PLP, Addressing::Implied),
-
-      /// ジャンプ命令
-      // JMP
-      0x4c => Instruction(3, Opcode::JMP, Addressing::Absolute),
-      0x6c => Instruction(5, Opcode::JMP, Addressing::Indirect),
-      // JSR, RTS, RTI
-      0x20 => Instruction(6, Opcode::JSR, Addressing::Absolute),
-      0x60 => Instruction(6, Opcode::RTS, Addressing::Implied),
-      0x40 => Instruction(6, Opcode::RTI, Addressing::Implied),
-
-      /// 分岐命令
-      // BCC, BCS, BEQ, BMI, BNE, BPL, BVC, BVS
-      0x90 => Instruction(2, Opcode::BCC, Addressing::Relative),
-      0xb0 => Instruction(2, Opcode::BCS, Addressing::Relative),
-      0xf0 => Instruction(2, Opcode::BEQ, Addressing::Relative),
-      0x30 => Instruction(2, Opcode::BMI, Addressing::Relative),
-      0xd0 => Instruction(2, Opcode::BNE, Addressing::Relative),
-      0x10 => Instruction(2, Opcode::BPL, Addressing::Relative),
-      0x50 => Instruction(2, Opcode::BVC, Addressing::Relative),
-      0x70 => Instruction(2, Opcode::BVS, Addressing::Relative),
-
-      /// フラグ変更命令
-      // CLC, CLD, CLI, CLV, SEC, SED, SEI
-      0x18 => Instruction(2, Opcode::CLC, Addressing::Implied),
-      0xd8 => Instruction(2, Opcode::CLD, Addressing::Implied),
-      0x58 => Instruction(2, Opcode::CLI, Addressing::Implied),
-      0xb8 => Instruction(2, Opcode::CLV, Addressing::Implied),
-      0x38 => Instruction(2, Opcode::SEC, Addressing::Implied),
-      0xf8 => Instruction(2, Opcode::SED, Addressing::Implied),
-      0x78 => Instruction(2, Opcode::SEI, Addressing::Implied),
-
-      /// その他
-      // BRK
-      0x00 => Instruction(7, Opcode::BRK, Addressing::Implied),
-      // NOP
-      0x1a => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x3a => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x5a => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x7a => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0xda => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0xea => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0xfa => Instruction(1, Opcode::NOP, Addressing::Implied),
-      // SKB
-      0x80 => Instruction(2, Opcode::SKB, Addressing::Immediate),
-      0x82 => Instruction(2, Opcode::SKB, Addressing::Immediate),
-      0x89 => Instruction(2, Opcode::SKB, Addressing::Immediate),
-      0xc2 => Instruction(2, Opcode::SKB, Addressing::Immediate),
-      0xe2 => Instruction(2, Opcode::SKB, Addressing::Immediate),
-
-      // unofficial_opcodesが必要らしい
-      /// RMW 命令
-      // DCP
-      0xc3 => Instruction(8, Opcode::DCP, Addressing::IndirectX),
-      0xc7 => Instruction(5, Opcode::DCP, Addressing::Zeropage),
-      0xcf => Instruction(6, Opcode::DCP, Addressing::Absolute),
-      0xd3 => Instruction(8, Opcode::DCP, Addressing::IndirectY),
-      0xd7 => Instruction(6, Opcode::DCP, Addressing::ZeropageX),
-      0xdb => Instruction(7, Opcode::DCP, Addressing::AbsoluteY),
-      0xdf => Instruction(7, Opcode::DCP, Addressing::AbsoluteX),
-      // ISC
-      0xe3 => Instruction(8, Opcode::ISC, Addressing::IndirectX),
-      0xe7 => Instruction(5, Opcode::ISC, Addressing::Zeropage),
-      0xef => Instruction(6, Opcode::ISC, Addressing::Absolute),
-      0xf3 => Instruction(8, Opcode::ISC, Addressing::IndirectY),
-      0xf7 => Instruction(6, Opcode::ISC, Addressing::ZeropageX),
-      0xfb => Instruction(7, Opcode::ISC, Addressing::AbsoluteX),
-      0xff => Instruction(7, Opcode::ISC, Addressing::AbsoluteX),
-      // RLA
-      0x23 => Instruction(8, Opcode::RLA, Addressing::IndirectX),
-      0x27 => Instruction(5, Opcode::RLA, Addressing::Zeropage),
-      0x2f => Instruction(6, Opcode::RLA, Addressing::Absolute),
-      0x33 => Instruction(8, Opcode::RLA, Addressing::IndirectY),
-      0x37 => Instruction(6, Opcode::RLA, Addressing::ZeropageX),
-      0x3b => Instruction(7, Opcode::RLA, Addressing::AbsoluteY),
-      0x3f => Instruction(7, Opcode::RLA, Addressing::AbsoluteX),
-      // RRA
-      0x63 => Instruction(8, Opcode::RRA, Addressing::IndirectX),
-      0x67 => Instruction(5, Opcode::RRA, Addressing::Zeropage),
-      0x6f => Instruction(6, Opcode::RRA, Addressing::Absolute),
-      0x73 => Instruction(8, Opcode::RRA, Addressing::IndirectY),
-      0x77 => Instruction(6, Opcode::RRA, Addressing::ZeropageX),
-      0x7b => Instruction(7, Opcode::RRA, Addressing::AbsoluteY),
-      0x7f => Instruction(7, Opcode::RRA, Addressing::AbsoluteX),
-      // SLO
-      0x03 => Instruction(8, Opcode::SLO, Addressing::IndirectX),
-      0x07 => Instruction(5, Opcode::SLO, Addressing::Zeropage),
-      0x0f => Instruction(6, Opcode::SLO, Addressing::Absolute),
-      0x13 => Instruction(8, Opcode::SLO, Addressing::IndirectY),
-      0x17 => Instruction(6, Opcode::SLO, Addressing::ZeropageX),
-      0x1b => Instruction(7, Opcode::SLO, Addressing::AbsoluteY),
-      0x1f => Instruction(7, Opcode::SLO, Addressing::AbsoluteX),
-      // SRE
-      0x43 => Instruction(8, Opcode::SRE, Addressing::IndirectX),
-      0x47 => Instruction(5, Opcode::SRE, Addressing::Zeropage),
-      0x4f => Instruction(6, Opcode::SRE, Addressing::Absolute),
-      0x53 => Instruction(8, Opcode::SRE, Addressing::IndirectY),
-      0x57 => Instruction(6, Opcode::SRE, Addressing::ZeropageX),
-      0x5b => Instruction(7, Opcode::SRE, Addressing::AbsoluteY),
-      0x5f => Instruction(7, Opcode::SRE, Addressing::AbsoluteX),
-
-      /// 結合演算子
-      0x4b => Instruction(2, Opcode::ALR, Addressing::Immediate),
-      0x0b => Instruction(2, Opcode::ANC, Addressing::Immediate),
-      0x6b => Instruction(2, Opcode::ARR, Addressing::Immediate),
-      0xcb => Instruction(2, Opcode::AXS, Addressing::Immediate),
-      // LAX
-      0xa3 => Instruction(6, Opcode::LAX, Addressing::IndirectX),
-      0xa7 => Instruction(3, Opcode::LAX, Addressing::Zeropage),
-      0xaf => Instruction(4, Opcode::LAX, Addressing::Absolute),
-      0xb3 => Instruction(5, Opcode::LAX, Addressing::IndirectY),
-      0xb7 => Instruction(4, Opcode::LAX, Addressing::ZeropageY),
-      0xbf => Instruction(4, Opcode::LAX, Addressing::AbsoluteY),
-      // SAX
-      0x83 => Instruction(6, Opcode::SAX, Addressing::IndirectX),
-      0x87 => Instruction(3, Opcode::SAX, Addressing::Zeropage),
-      0x8f => Instruction(4, Opcode::SAX, Addressing::Absolute),
-      0x97 => Instruction(4, Opcode::SAX, Addressing::ZeropageY),
-      // IGN
-      0x0c => Instruction(4, Opcode::IGN, Addressing::Absolute),
-
-      // 4 or 5 cycle と書いてあったけど4っぽい?
-      0x1c => Instruction(4, Opcode::IGN, Addressing::AbsoluteX),
-      0x3c => Instruction(4, Opcode::IGN, Addressing::AbsoluteX),
-      0x5c => Instruction(4, Opcode::IGN, Addressing::AbsoluteX),
-      0x7c => Instruction(4, Opcode::IGN, Addressing::AbsoluteX),
-      0xdc => Instruction(4, Opcode::IGN, Addressing::AbsoluteX),
-      0xfc => Instruction(4, Opcode::IGN, Addressing::AbsoluteX),
-
-      0x04 => Instruction(3, Opcode::IGN, Addressing::Zeropage),
-      0x44 => Instruction(3, Opcode::IGN, Addressing::Zeropage),
-      0x64 => Instruction(3, Opcode::IGN, Addressing::Zeropage),
-
-      0x14 => Instruction(4, Opcode::IGN, Addressing::ZeropageX),
-      0x34 => Instruction(4, Opcode::IGN, Addressing::ZeropageX),
-      0x54 => Instruction(4, Opcode::IGN, Addressing::ZeropageX),
-      0x74 => Instruction(4, Opcode::IGN, Addressing::ZeropageX),
-      0xd4 => Instruction(4, Opcode::IGN, Addressing::ZeropageX),
-      0xf4 => Instruction(4, Opcode::IGN, Addressing::ZeropageX),
-
-      // Unknown code となったものをNOPとしておく
-      0x02 => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x12 => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x22 => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x32 => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x42 => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x52 => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x62 => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x72 => Instruction(1, Opcode::NOP, Addressing::Implied),
-      0x92 => Instruction(1, Opcode::NOP, Addressing::Implied),
-
-      // TODO: たまに未知の値を検出するとパニックするのでNOPで対応
-      // TODO: 実装方法が正しいのかは不明 [要検証]
-      // _ => panic!(format!("Unknown code 0x{:>02x}", code)),
-      _ => {
-        let text = format!("Unknown code 0x{:>02x}", code);
+pub fn decode(code: u8) -> &'static OpcodeInfo {
+  &OPCODES[code as usize]
+}
 
-        // 本来はすべてpanicさせるべき(?)
-        if false {
-          panic!(text);
-        }
-        // println!("{}", text);
-        Instruction(1, Opcode::NOP, Addressing::Implied)
-      }
+impl Cpu {
+  // 1サイクル前の状態を残して NMI, IRQ の信号を読む
+  pub fn poll_interrupt<B: Bus>(&mut self, bus: &B) {
+    self.prev_need_nmi = self.need_nmi;
+    self.prev_run_irq = self.run_irq;
+
+    let nmi = bus.nmi();
+    if nmi && !self.nmi_line {
+      self.need_nmi = true;
     }
-  }
+    self.nmi_line = nmi;
 
-  pub fn interrupt(&mut self, machine: &mut machine::Machine, intr: Interrupt) {
-    println!("detect interrupt {:?}", intr);
+    // I フラグが立っていたら IRQ は無視する
+    self.run_irq = bus.irq() && !self.read_i_flag();
+  }
 
+  // 割り込み処理 (7 cycle)
+  // https://wiki.nesdev.com/w/index.php/CPU_interrupts
+  //
+  // | Interrupt | Vector | 積むステータスの B |
+  // | - | - | - |
+  // | NMI | $fffa | 0 |
+  // | RESET | $fffc | (積まない) |
+  // | IRQ | $fffe | 0 |
+  // | BRK | $fffe | 1 |
+  //
+  // BRK, IRQ の途中で NMI が来ると NMI のベクタに乗っ取られる
+  pub fn interrupt<B: Bus>(&mut self, bus: &mut B, intr: Interrupt) {
+    // BRK は命令のフェッチとパディングの読み出しが済んでいる
     match intr {
-      Interrupt::RESET => {
-        self.set_i_flag(ON);
-        // TODO: PCの下位バイトを$FFFCから
-        // 上位バイトを$FFFDからフェッチ
-      }
-
-      Interrupt::NMI => {
-        self.set_b_flag(OFF);
-
-        self.push_stack(machine, (self.pc >> 8) as u8);
-        self.push_stack(machine, (self.pc & 255) as u8);
-        self.push_stack(machine, self.p);
-
-        self.set_i_flag(ON);
+      Interrupt::BRK => self.pc = self.pc.wrapping_add(1),
+      _ => {
+        self.dummy_read(bus, self.pc as usize);
+        self.dummy_read(bus, self.pc as usize);
       }
+    }
 
-      Interrupt::IRQ => {
-        if self.read_i_flag() == 0 {
-          self.set_b_flag(OFF);
-
-          self.push_stack(machine, (self.pc >> 8) as u8);
-          self.push_stack(machine, (self.pc & 255) as u8);
-          self.push_stack(machine, self.p);
-
-          self.set_i_flag(ON);
+    let vector = match intr {
+      Interrupt::RESET => {
+        // 書き込みは行われず, SP だけ3つ減る
+        for _ in 0..3 {
+          self.dummy_read(bus, 0x100 | self.sp as usize);
+          self.sp = self.sp.wrapping_sub(1);
         }
+        0xfffc
       }
 
-      Interrupt::BRK => {
-        if self.read_i_flag() == 0 {
-          self.set_b_flag(ON);
-          self.pc += 1;
-
-          self.push_stack(machine, (self.pc >> 8) as u8);
-          self.push_stack(machine, (self.pc & 255) as u8);
-          self.push_stack(machine, self.p);
-          self.set_i_flag(ON);
-        }
-      }
+      _ => {
+        self.push_stack_16(bus, self.pc);
 
-      _ => panic!("Unknown interrupt"),
-    }
+        // ベクタを読む前に NMI が来ていたら乗っ取られる
+        let vector = if matches!(intr, Interrupt::NMI) || self.need_nmi {
+          self.need_nmi = false;
+          0xfffa
+        } else {
+          0xfffe
+        };
 
-    // set pc
-    let low = match intr {
-      Interrupt::RESET => 0xfffc,
-      Interrupt::BRK => 0xfffe,
-      _ => 0
+        let b = match intr {
+          Interrupt::BRK => 0x10,
+          _ => 0x00,
+        };
+        self.push_stack(bus, (self.p & !0x10) | 0x20 | b);
+        vector
+      }
     };
 
-    let high = match intr {
-      Interrupt::RESET => 0xfffd,
-      Interrupt::BRK => 0xffff,
-      _ => 0
-    };
+    self.set_i_flag(ON);
 
-    let pc = ((machine.read(high) as u16) << 8) | (machine.read(low) as u16);
-    println!("low {}, high {}, pc {}", low, high, pc);
-    self.pc = pc;
-  }
+    let low = self.read(bus, vector) as u16;
+    let high = self.read(bus, vector + 1) as u16;
+    self.pc = (high << 8) | low;
 
-  // フラグ(n-bit目)の読み出し
-  fn read_c_flag(&self) -> u8 {
-    self.p & (1 << 0)
+    // 割り込みを処理したら次の命令の後ではもう処理しない
+    self.prev_need_nmi = false;
+    self.prev_run_irq = false;
   }
 
-  fn read_z_flag(&self) -> u8 {
-    self.p & (1 << 1)
+  // フラグ(n-bit目)の読み出し
+  fn read_c_flag(&self) -> bool {
+    self.p & (1 << 0) != 0
   }
 
-  fn read_i_flag(&self) -> u8 {
-    self.p & (1 << 2)
+  fn read_z_flag(&self) -> bool {
+    self.p & (1 << 1) != 0
   }
 
-  fn read_d_flag(&self) -> u8 {
-    self.p & (1 << 3)
+  fn read_i_flag(&self) -> bool {
+    self.p & (1 << 2) != 0
   }
 
-  fn read_b_flag(&self) -> u8 {
-    self.p & (1 << 4)
+  fn read_d_flag(&self) -> bool {
+    self.p & (1 << 3) != 0
   }
 
-  fn read_v_flag(&self) -> u8 {
-    self.p & (1 << 6)
+  fn read_v_flag(&self) -> bool {
+    self.p & (1 << 6) != 0
   }
 
-  fn read_n_flag(&self) -> u8 {
-    self.p & (1 << 7)
+  fn read_n_flag(&self) -> bool {
+    self.p & (1 << 7) != 0
   }
 
   // フラグ操作
@@ -582,13 +593,7 @@ impl Cpu {
     };
   }
 
-  fn set_b_flag(&mut self, stat: bool) {
-    self.p = if stat {
-      self.p | 0x10 // 1 << 4
-    } else {
-      self.p & (!0x10)
-    };
-  }
+  // 1 << 4 (B) はスタックに積んだ値にだけ現れる
 
   // 1 << 5 は常に1
 
@@ -608,64 +613,133 @@ impl Cpu {
     };
   }
 
-  fn fetch_8bit(&mut self, machine: &mut machine::Machine) -> u8 {
+  // 結果の値から N, Z をまとめて更新する
+  fn set_nz_flag(&mut self, val: u8) {
+    self.set_n_flag(val & (1 << 7) == (1 << 7));
+    self.set_z_flag(val == 0);
+  }
+
+  fn fetch_8bit<B: Bus>(&mut self, bus: &mut B) -> u8 {
     // println!("inc pc");
-    let val = machine.read(self.pc as usize);
-    self.pc += 1;
+    let val = self.read(bus, self.pc as usize);
+    self.pc = self.pc.wrapping_add(1);
     val
   }
 
-  fn fetch_16bit(&mut self, machine: &mut machine::Machine) -> u16 {
-    let low = self.fetch_8bit(machine) as u16;
-    let high = self.fetch_8bit(machine) as u16;
+  fn fetch_16bit<B: Bus>(&mut self, bus: &mut B) -> u16 {
+    let low = self.fetch_8bit(bus) as u16;
+    let high = self.fetch_8bit(bus) as u16;
+    (high << 8) | low
+  }
+
+  // ゼロページ上のポインタを読む (上位バイトも $00 ~ $ff に収まる)
+  fn read_zeropage_16bit<B: Bus>(&mut self, bus: &mut B, addr: u8) -> u16 {
+    let low = self.read(bus, addr as usize) as u16;
+    let high = self.read(bus, addr.wrapping_add(1) as usize) as u16;
     (high << 8) | low
   }
 
+  // インデックスを足して, ページを跨いだかを記録する
+  // 実機は上位バイトを繰り上げる前のアドレスを一度読む (書き込み命令では常に読む)
+  fn add_index<B: Bus>(
+    &mut self,
+    bus: &mut B,
+    base: u16,
+    index: u8,
+    write: bool,
+  ) -> u16 {
+    let addr = base.wrapping_add(index as u16);
+    self.page_crossed = (base ^ addr) & 0xff00 != 0;
+    if self.page_crossed || write {
+      self.dummy_read(bus, ((base & 0xff00) | (addr & 0x00ff)) as usize);
+    }
+    addr
+  }
+
   // アドレスを返す
   // https://wiki.nesdev.com/w/index.php/CPU_addressing_modes
-  fn fetch_operand(&mut self, addr_mode: Addressing, machine: &mut machine::Machine) -> u16 {
+  //
+  // | Addressing | Address | 一周する範囲 |
+  // | - | - | - |
+  // | ZeropageX, ZeropageY | (d + X) & $ff | ゼロページ内 |
+  // | AbsoluteX, AbsoluteY | a + X | $ffff の次は $0000 |
+  // | IndirectX | [(d + X) & $ff] | ポインタの上位バイトもゼロページ内 |
+  // | IndirectY | [d] + Y | ポインタはゼロページ内, Y は読み出したアドレスに足す |
+  // | Indirect | [a] | 上位バイトは同じページから読む ($xxff の次は $xx00) |
+  fn fetch_operand<B: Bus>(&mut self, addr_mode: Addressing, bus: &mut B) -> u16 {
+    self.fetch_address(addr_mode, bus, false)
+  }
+
+  // ストア, RMW 命令のアドレスを返す
+  fn fetch_write_operand<B: Bus>(&mut self, addr_mode: Addressing, bus: &mut B) -> u16 {
+    self.fetch_address(addr_mode, bus, true)
+  }
+
+  fn fetch_address<B: Bus>(
+    &mut self,
+    addr_mode: Addressing,
+    bus: &mut B,
+    write: bool,
+  ) -> u16 {
     match addr_mode {
-      Addressing::Immediate => self.fetch_8bit(machine) as u16,
+      Addressing::Immediate => self.fetch_8bit(bus) as u16,
 
-      Addressing::Zeropage => self.fetch_8bit(machine) as u16,
+      Addressing::Zeropage => self.fetch_8bit(bus) as u16,
 
-      Addressing::ZeropageX => (self.fetch_8bit(machine) + self.x) as u16,
+      Addressing::ZeropageX => {
+        let base = self.fetch_8bit(bus);
+        self.dummy_read(bus, base as usize);
+        base.wrapping_add(self.x) as u16
+      }
 
-      Addressing::ZeropageY => (self.fetch_8bit(machine) + self.y) as u16,
+      Addressing::ZeropageY => {
+        let base = self.fetch_8bit(bus);
+        self.dummy_read(bus, base as usize);
+        base.wrapping_add(self.y) as u16
+      }
 
-      Addressing::Absolute => self.fetch_16bit(machine),
+      Addressing::Absolute => self.fetch_16bit(bus),
 
-      Addressing::AbsoluteX => self.fetch_16bit(machine) + self.x as u16,
+      Addressing::AbsoluteX => {
+        let base = self.fetch_16bit(bus);
+        self.add_index(bus, base, self.x, write)
+      }
 
-      Addressing::AbsoluteY => self.fetch_16bit(machine) + self.y as u16,
+      Addressing::AbsoluteY => {
+        let base = self.fetch_16bit(bus);
+        self.add_index(bus, base, self.y, write)
+      }
 
-      Addressing::Relative => ((self.fetch_8bit(machine) as i8) as i32 + self.pc as i32) as u16,
+      Addressing::Relative => {
+        let offset = self.fetch_8bit(bus) as i8;
+        self.pc.wrapping_add(offset as u16)
+      }
 
       Addressing::Indirect => {
-        let addr_low = self.fetch_8bit(machine) as u16;
-        let addr_high = self.fetch_8bit(machine) as u16;
+        let addr_low = self.fetch_8bit(bus) as u16;
+        let addr_high = self.fetch_8bit(bus) as u16;
 
-        let addr = ((addr_high << 8) | addr_low) as usize;
-        let data_low = machine.read(addr) as u16;
-        let data_high = machine.read(addr + 1) as u16;
+        // 6502 のバグで上位バイトは同じページから読む ($xxff の次は $xx00)
+        let addr = (addr_high << 8) | addr_low;
+        let data_low = self.read(bus, addr as usize) as u16;
+        let addr_next = (addr & 0xff00) | (addr.wrapping_add(1) & 0x00ff);
+        let data_high = self.read(bus, addr_next as usize) as u16;
 
-        ((data_high << 8) | data_low) as u16
+        (data_high << 8) | data_low
       }
 
       Addressing::IndirectX => {
-        let addr = (self.fetch_8bit(machine) + self.x) as usize;
-        let data_low = machine.read(addr) as u16;
-        let data_high = machine.read(addr + 1) as u16;
-
-        ((data_high << 8) | data_low) as u16
+        let base = self.fetch_8bit(bus);
+        self.dummy_read(bus, base as usize);
+        let addr = base.wrapping_add(self.x);
+        self.read_zeropage_16bit(bus, addr)
       }
 
       Addressing::IndirectY => {
-        let addr = (self.fetch_8bit(machine) + self.y) as usize;
-        let data_low = machine.read(addr) as u16;
-        let data_high = machine.read(addr + 1) as u16;
-
-        ((data_high << 8) | data_low) as u16
+        // Y はポインタではなく読み出したアドレスに足す
+        let addr = self.fetch_8bit(bus);
+        let base = self.read_zeropage_16bit(bus, addr);
+        self.add_index(bus, base, self.y, write)
       }
 
       _ => 0, // Implied, Accumulator
@@ -673,227 +747,253 @@ impl Cpu {
   }
 
   // データを返す
-  fn fetch_data(&mut self, addr_mode: Addressing, machine: &mut machine::Machine) -> u8 {
+  fn fetch_data<B: Bus>(&mut self, addr_mode: Addressing, bus: &mut B) -> u8 {
     match addr_mode {
       Addressing::Implied => 0,
 
       Addressing::Accumulator => self.a,
 
       Addressing::Immediate => {
-        let data = self.fetch_operand(addr_mode, machine);
+        let data = self.fetch_operand(addr_mode, bus);
         data as u8
       }
 
       _ => {
-        let data = self.fetch_operand(addr_mode, machine);
-        machine.read(data as usize)
+        let data = self.fetch_operand(addr_mode, bus);
+        self.read(bus, data as usize)
       }
     }
   }
 
-  // 実行したいニャンね
-  pub fn exec(&mut self, machine: &mut machine::Machine) -> (u8, u8) {
-    // 命令をfetchする
-    // TODO: Overflowの修正(mirror?)
-    let code_addr = if self.pc as usize >= machine.prg_bytes { self.pc as usize - machine.prg_bytes } else { self.pc as usize };
-    let code = machine.prg_rom[code_addr];
-
-    let pc_tmp = self.pc;
-    self.pc += 1;
-
-    let Instruction(cycle, opcode, addr_mode) = self.convert(code);
+  // Read-Modify-Write 命令 (ASL, LSR, ROL, ROR, INC, DEC)
+  // 読んだ値に f を適用して書き戻し、結果を返す
+  fn modify<B: Bus, F>(&mut self, addr_mode: Addressing, bus: &mut B, f: F) -> u8
+  where
+    F: Fn(&mut Self, u8) -> u8,
+  {
+    let res = if addr_mode == Addressing::Accumulator {
+      let res = f(self, self.a);
+      self.a = res;
+      res
+    } else {
+      let addr = self.fetch_write_operand(addr_mode, bus) as usize;
+      let m = self.read(bus, addr);
+      // 実機は読んだ値を一度そのまま書き戻す
+      self.dummy_write(bus, addr, m);
+      let res = f(self, m);
+      self.write(bus, addr, res);
+      res
+    };
 
-    // http://obelisk.me.uk/6502/reference.html
-    // http://pgate1.at-ninja.jp/NES_on_FPGA/nes_cpu.htm#instruction
-    match opcode {
-      // 数値演算, 論理演算
-      Opcode::ADC => {
-        let a = self.a;
-        let m = self.fetch_data(addr_mode, machine);
-        let c = self.read_c_flag();
-        let res = (a + m + c) & 0xff;
+    self.set_nz_flag(res);
+    res
+  }
 
-        self.set_c_flag((a + m + c) > 0xff);
-        self.set_z_flag(res == 0);
-        self.set_v_flag((a ^ res) & (m ^ res) & (1 << 7) == 1 << 7);
-        self.set_n_flag((res & (1 << 7)) == 1 << 7);
+  // A + M + C (SBC は M を反転して足す)
+  fn add_with_carry(&mut self, m: u8) {
+    let a = self.a;
+    let sum = a as u16 + m as u16 + self.read_c_flag() as u16;
+    let res = sum as u8;
+
+    self.set_c_flag(sum > 0xff);
+    // 同じ符号同士を足して符号が変わったらオーバーフロー
+    self.set_v_flag((a ^ res) & (m ^ res) & (1 << 7) == 1 << 7);
+    self.set_nz_flag(res);
+    self.a = res;
+  }
 
-        self.a = res;
-      }
+  // 10進モード (BCD) が有効か (2A03 では D フラグは無視される)
+  fn decimal(&self) -> bool {
+    self.variant == Variant::Mos6502 && self.read_d_flag()
+  }
 
-      Opcode::SBC => {
-        let a = self.a;
-        let m = self.fetch_data(addr_mode, machine);
-        let c = self.read_c_flag();
-        let res = a - m - !c;
+  // ADC (ADC, RRA)
+  // http://www.6502.org/tutorials/decimal_mode.html
+  fn adc(&mut self, m: u8) {
+    if !self.decimal() {
+      self.add_with_carry(m);
+      return;
+    }
 
-        self.set_n_flag((res & (1 << 7)) == 1 << 7);
-        self.set_v_flag((a ^ m) & (m ^ res) & (1 << 7) == 1 << 7);
-        self.set_z_flag(res == 0);
-        self.set_c_flag(res >= 0);
-        self.a = res;
-      }
+    let a = self.a;
+    let c = self.read_c_flag() as u16;
 
-      Opcode::AND => {
-        let a = self.a;
-        let m = self.fetch_data(addr_mode, machine);
-        let res = a & m;
+    // Z は2進数での結果から決まる
+    self.set_z_flag(a.wrapping_add(m).wrapping_add(c as u8) == 0);
 
-        self.set_z_flag(a == 0);
-        self.set_n_flag((res & (1 << 7)) == 1 << 7);
-        self.a = res;
-      }
+    let mut low = (a & 0x0f) as u16 + (m & 0x0f) as u16 + c;
+    if low >= 0x0a {
+      low = ((low + 0x06) & 0x0f) + 0x10;
+    }
 
-      Opcode::ORA => {
-        let a = self.a;
-        let m = self.fetch_data(addr_mode, machine);
-        let res = a | m;
+    // N, V は上位の桁を補正する前の値から決まる
+    let res = (a & 0xf0) as u16 + (m & 0xf0) as u16 + low;
+    self.set_n_flag(res & 0x80 == 0x80);
+    self.set_v_flag((a ^ res as u8) & (m ^ res as u8) & 0x80 == 0x80);
 
-        self.set_z_flag(a == 0);
-        self.set_n_flag((res & (1 << 7)) == 1 << 7);
-        self.a = res;
-      }
+    let res = if res >= 0xa0 { res + 0x60 } else { res };
+    self.set_c_flag(res >= 0x100);
+    self.a = res as u8;
+  }
 
-      Opcode::EOR => {
-        let a = self.a;
-        let m = self.fetch_data(addr_mode, machine);
-        let res = a ^ m;
+  // SBC (SBC, ISC)
+  // フラグは 10進モードでも2進数での結果から決まる
+  fn sbc(&mut self, m: u8) {
+    let a = self.a;
+    let c = self.read_c_flag() as i16;
+    self.add_with_carry(!m);
 
-        self.set_z_flag(a == 0);
-        self.set_n_flag((res & (1 << 7)) == 1 << 7);
-        self.a = res;
+    if self.decimal() {
+      let mut low = (a & 0x0f) as i16 - (m & 0x0f) as i16 + c - 1;
+      if low < 0 {
+        low = ((low - 0x06) & 0x0f) - 0x10;
       }
 
-      // bitシフト, bitローテーション
-      Opcode::ASL => {
-        // モード別でアドレスをFetchしてくる
-        let a = self.a;
-        let res = a << 1;
-
-        self.set_n_flag((res >> 1) & 1 == 1);
-        self.set_z_flag(res == 0);
-        self.set_c_flag((a >> 1) & 1 == 1);
-
-        if addr_mode == Addressing::Accumulator {
-          self.a = res;
-        } else {
-          let addr = self.fetch_operand(addr_mode, machine) as usize;
-          machine.write(addr, res);
-        }
+      let mut res = (a & 0xf0) as i16 - (m & 0xf0) as i16 + low;
+      if res < 0 {
+        res -= 0x60;
       }
+      self.a = res as u8;
+    }
+  }
 
-      Opcode::LSR => {
-        let a = self.a;
-        let res = a >> 1;
-
-        self.set_n_flag((res >> 7) & 1 == 1 << 7);
-        self.set_z_flag(res == 0);
-        self.set_c_flag((a >> 0) & 1 == 1);
+  fn compare(&mut self, reg: u8, m: u8) {
+    self.set_c_flag(reg >= m);
+    self.set_nz_flag(reg.wrapping_sub(m));
+  }
 
-        if addr_mode == Addressing::Accumulator {
-          self.a = res;
-        } else {
-          let addr = self.fetch_operand(addr_mode, machine) as usize;
-          machine.write(addr, res);
-        }
-      }
+  fn branch<B: Bus>(&mut self, addr_mode: Addressing, bus: &mut B, cond: bool) {
+    let addr = self.fetch_operand(addr_mode, bus);
+    if cond {
+      // 分岐すると +1, 分岐先が別のページならさらに +1
+      self.dummy_read(bus, self.pc as usize);
+      self.extra_cycles += 1;
+      if (self.pc ^ addr) & 0xff00 != 0 {
+        self.dummy_read(bus, ((self.pc & 0xff00) | (addr & 0x00ff)) as usize);
+        self.extra_cycles += 1;
+      }
+      self.pc = addr;
+    }
+  }
 
-      Opcode::ROL => {
-        let a = self.a;
-        let res = a << 1;
+  // AHX, SHX, SHY, TAS の書き込み
+  // 値に (元のアドレスの上位バイト + 1) を AND して書き込む
+  // インデックスでページを跨いだときは上位バイトが書き込む値に置き換わる
+  fn store_high_and<B: Bus>(
+    &mut self,
+    addr_mode: Addressing,
+    bus: &mut B,
+    index: u8,
+    val: u8,
+  ) {
+    let addr = self.fetch_write_operand(addr_mode, bus);
+    let base = addr.wrapping_sub(index as u16);
+    let res = val & ((base >> 8) as u8).wrapping_add(1);
+
+    let addr = if (base ^ addr) & 0xff00 != 0 {
+      ((res as u16) << 8) | (addr & 0xff)
+    } else {
+      addr
+    };
+    self.write(bus, addr as usize, res);
+  }
 
-        self.set_n_flag((res >> 7) & 1 == 1 << 7);
-        self.set_z_flag(res == 0);
-        self.set_c_flag((a >> 0) & 1 == 1 << 7);
+  // 実行したいニャンね
+  //
+  // (実行にかかったサイクル数, 命令コード) を返す
+  pub fn exec<B: Bus>(&mut self, bus: &mut B) -> (u16, u8) {
+    // 命令をfetchする
+    let code = self.read(bus, self.pc as usize);
+    self.pc = self.pc.wrapping_add(1);
+
+    let info = decode(code);
+    let (opcode, addr_mode) = (info.opcode, info.mode);
+    self.page_crossed = false;
+    self.extra_cycles = 0;
+    let i_flag = self.read_i_flag();
+
+    // 1バイト命令も次のバイトを読む (BRK はこれがパディングの読み出しになる)
+    if addr_mode == Addressing::Implied || addr_mode == Addressing::Accumulator {
+      self.dummy_read(bus, self.pc as usize);
+    }
 
-        if addr_mode == Addressing::Accumulator {
-          self.a = res;
-        } else {
-          let addr = self.fetch_operand(addr_mode, machine) as usize;
-          machine.write(addr, res);
-        }
+    // http://obelisk.me.uk/6502/reference.html
+    // http://pgate1.at-ninja.jp/NES_on_FPGA/nes_cpu.htm#instruction
+    match opcode {
+      // 数値演算, 論理演算
+      Opcode::ADC => {
+        let m = self.fetch_data(addr_mode, bus);
+        self.adc(m);
       }
 
-      Opcode::ROR => {
-        let a = self.a;
-        let res = a >> 1;
-
-        self.set_n_flag((res >> 7) & 1 == 1 << 7);
-        self.set_z_flag(res == 0);
-        self.set_c_flag((a >> 0) & 1 == 1 << 7);
-
-        if addr_mode == Addressing::Accumulator {
-          self.a = res;
-        } else {
-          let addr = self.fetch_operand(addr_mode, machine) as usize;
-          machine.write(addr, res);
-        }
+      Opcode::SBC => {
+        // A - M - (1 - C) = A + !M + C
+        let m = self.fetch_data(addr_mode, bus);
+        self.sbc(m);
       }
 
-      // 条件分岐
-      Opcode::BCC => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        if self.read_c_flag() == 0 {
-          self.pc = addr;
-        }
+      Opcode::AND => {
+        let m = self.fetch_data(addr_mode, bus);
+        self.a &= m;
+        self.set_nz_flag(self.a);
       }
 
-      Opcode::BCS => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        if self.read_c_flag() == 1 {
-          self.pc = addr;
-        }
+      Opcode::ORA => {
+        let m = self.fetch_data(addr_mode, bus);
+        self.a |= m;
+        self.set_nz_flag(self.a);
       }
 
-      Opcode::BNE => {
-        // if addr_mode == Addressing::Relative { println!("{}", self.pc); }
-        let addr = self.fetch_operand(addr_mode, machine);
-        // if addr_mode == Addressing::Relative { println!("  {}", self.pc); }
-
-        if self.read_z_flag() == 0 {
-          // println!("addr diff {}", addr - self.pc);
-          self.pc = addr;
-        }
+      Opcode::EOR => {
+        let m = self.fetch_data(addr_mode, bus);
+        self.a ^= m;
+        self.set_nz_flag(self.a);
       }
 
-      Opcode::BEQ => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        if self.read_z_flag() == 1 {
-          self.pc = addr;
-        }
+      // bitシフト, bitローテーション
+      Opcode::ASL => {
+        self.modify(addr_mode, bus, |cpu, m| {
+          cpu.set_c_flag(m & (1 << 7) == 1 << 7);
+          m << 1
+        });
       }
 
-      Opcode::BVC => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        if self.read_v_flag() == 0 {
-          self.pc = addr;
-        }
+      Opcode::LSR => {
+        self.modify(addr_mode, bus, |cpu, m| {
+          cpu.set_c_flag(m & 1 == 1);
+          m >> 1
+        });
       }
 
-      Opcode::BVS => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        if self.read_v_flag() == 1 {
-          self.pc = addr;
-        }
+      Opcode::ROL => {
+        self.modify(addr_mode, bus, |cpu, m| {
+          let c = cpu.read_c_flag() as u8;
+          cpu.set_c_flag(m & (1 << 7) == 1 << 7);
+          (m << 1) | c
+        });
       }
 
-      Opcode::BPL => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        if self.read_n_flag() == 0 {
-          self.pc = addr;
-        }
+      Opcode::ROR => {
+        self.modify(addr_mode, bus, |cpu, m| {
+          let c = cpu.read_c_flag() as u8;
+          cpu.set_c_flag(m & 1 == 1);
+          (m >> 1) | (c << 7)
+        });
       }
 
-      Opcode::BMI => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        if self.read_n_flag() == 1 {
-          self.pc = addr;
-        }
-      }
+      // 条件分岐
+      Opcode::BCC => self.branch(addr_mode, bus, !self.read_c_flag()),
+      Opcode::BCS => self.branch(addr_mode, bus, self.read_c_flag()),
+      Opcode::BNE => self.branch(addr_mode, bus, !self.read_z_flag()),
+      Opcode::BEQ => self.branch(addr_mode, bus, self.read_z_flag()),
+      Opcode::BVC => self.branch(addr_mode, bus, !self.read_v_flag()),
+      Opcode::BVS => self.branch(addr_mode, bus, self.read_v_flag()),
+      Opcode::BPL => self.branch(addr_mode, bus, !self.read_n_flag()),
+      Opcode::BMI => self.branch(addr_mode, bus, self.read_n_flag()),
 
       // bit検査
       Opcode::BIT => {
-        let res = self.fetch_data(addr_mode, machine);
+        let res = self.fetch_data(addr_mode, bus);
 
         self.set_n_flag(res & (1 << 7) == 1 << 7);
         self.set_v_flag(res & (1 << 6) == 1 << 6);
@@ -902,118 +1002,82 @@ impl Cpu {
 
       // ジャンプ命令
       Opcode::JMP => {
-        let addr = self.fetch_operand(addr_mode, machine);
+        let addr = self.fetch_operand(addr_mode, bus);
         self.pc = addr;
       }
 
       Opcode::JSR => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        self.push_stack(machine, ((pc_tmp + 2) >> 8) as u8);
-        self.push_stack(machine, ((pc_tmp + 2) & 0xff) as u8);
+        let addr = self.fetch_operand(addr_mode, bus);
+        self.dummy_read(bus, 0x100 | self.sp as usize);
+        // JSR の最後のバイトのアドレスを積む
+        self.push_stack_16(bus, self.pc.wrapping_sub(1));
         self.pc = addr;
       }
 
       Opcode::RTS => {
-        let lower = self.pop_stack(machine) as u16;
-        let higher = self.pop_stack(machine) as u16;
-        let addr = ((higher << 8) | lower) + 1;
-        self.pc = addr;
+        self.dummy_read(bus, 0x100 | self.sp as usize);
+        self.pc = self.pop_stack_16(bus);
+        self.dummy_read(bus, self.pc as usize);
+        self.pc = self.pc.wrapping_add(1);
       }
 
       // 割り込み処理
       Opcode::BRK => {
-        self.interrupt(machine, Interrupt::BRK);
+        // I フラグに関係なく割り込む
+        self.interrupt(bus, Interrupt::BRK);
       }
 
-      Opcode::RTS => {
-        let stat = self.pop_stack(machine);
-        let lower = self.pop_stack(machine) as u16;
-        let higher = self.pop_stack(machine) as u16;
-
-        self.p = stat;
-        self.pc = (higher << 8) | lower;
+      Opcode::RTI => {
+        self.dummy_read(bus, 0x100 | self.sp as usize);
+        // B フラグは無視, bit 5 は常に1
+        self.p = (self.pop_stack(bus) & !0x10) | 0x20;
+        self.pc = self.pop_stack_16(bus);
       }
 
       // 比較演算
       Opcode::CMP => {
-        let m = self.fetch_data(addr_mode, machine);
-        let res = self.a - m;
-
-        self.set_n_flag((res >> 7) & 1 == 1);
-        self.set_z_flag(res == 0);
-        self.set_c_flag(self.a >= m);
+        let m = self.fetch_data(addr_mode, bus);
+        self.compare(self.a, m);
       }
 
       Opcode::CPX => {
-        let m = self.fetch_data(addr_mode, machine);
-        let res = self.x - m;
-
-        self.set_n_flag((res >> 7) & 1 == 1);
-        self.set_z_flag(res == 0);
-        self.set_c_flag(self.y >= m);
+        let m = self.fetch_data(addr_mode, bus);
+        self.compare(self.x, m);
       }
 
       Opcode::CPY => {
-        let m = self.fetch_data(addr_mode, machine);
-        let res = self.y - m;
-
-        self.set_n_flag((res >> 7) & 1 == 1);
-        self.set_z_flag(res == 0);
-        self.set_c_flag(self.x >= m);
+        let m = self.fetch_data(addr_mode, bus);
+        self.compare(self.y, m);
       }
 
       // ワンアゲ, ワンサゲ
       // https://twitter.com/yuki384love/status/1270365593800081408
       Opcode::INC => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        let m = self.fetch_data(addr_mode, machine);
-        let res = m + 1;
-
-        self.set_z_flag(res == 0);
-        self.set_n_flag((res >> 7) & 1 == (1 << 7));
-        machine.write(addr as usize, res);
+        self.modify(addr_mode, bus, |_, m| m.wrapping_add(1));
       }
 
       Opcode::DEC => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        let m = self.fetch_data(addr_mode, machine);
-        let res = m - 1;
-
-        self.set_z_flag(res == 0);
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        machine.write(addr as usize, res);
+        self.modify(addr_mode, bus, |_, m| m.wrapping_sub(1));
       }
 
       Opcode::INX => {
-        let res = self.x + 1;
-
-        self.set_z_flag(res == 0);
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.x = res;
+        self.x = self.x.wrapping_add(1);
+        self.set_nz_flag(self.x);
       }
 
       Opcode::DEX => {
-        let res = self.x - 1;
-
-        self.set_z_flag(res == 0);
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.x = res;
+        self.x = self.x.wrapping_sub(1);
+        self.set_nz_flag(self.x);
       }
 
       Opcode::INY => {
-        let res = self.y + 1;
-
-        self.set_z_flag(res == 0);
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.y = res;
+        self.y = self.y.wrapping_add(1);
+        self.set_nz_flag(self.y);
       }
 
       Opcode::DEY => {
-        let res = self.y.wrapping_sub(1);
-
-        self.set_z_flag(res == 0);
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.y = res;
+        self.y = self.y.wrapping_sub(1);
+        self.set_nz_flag(self.y);
       }
 
       // フラグ操作
@@ -1047,149 +1111,275 @@ impl Cpu {
 
       // ロード
       Opcode::LDA => {
-        let m = self.fetch_data(addr_mode, machine);
-
-        self.set_n_flag(m & (1 << 7) == (1 << 7));
-        self.set_z_flag(m == 0);
-        self.a = m;
+        self.a = self.fetch_data(addr_mode, bus);
+        self.set_nz_flag(self.a);
       }
 
       Opcode::LDX => {
-        // if addr_mode == Addressing::Immediate { println!("{}", pc_tmp); }
-        let m = self.fetch_data(addr_mode, machine);
-        // if addr_mode == Addressing::Immediate { println!("   {}", self.pc); }
-
-        self.set_n_flag(m & (1 << 7) == (1 << 7));
-        self.set_z_flag(m == 0);
-        self.x = m;
+        self.x = self.fetch_data(addr_mode, bus);
+        self.set_nz_flag(self.x);
       }
 
       Opcode::LDY => {
-        let m = self.fetch_data(addr_mode, machine);
-
-        self.set_n_flag(m & (1 << 7) == (1 << 7));
-        self.set_z_flag(m == 0);
-        self.y = m;
+        self.y = self.fetch_data(addr_mode, bus);
+        self.set_nz_flag(self.y);
       }
 
       // ストア
       Opcode::STA => {
-        let addr = self.fetch_operand(addr_mode, machine) as usize;
-        machine.write(addr, self.a);
+        let addr = self.fetch_write_operand(addr_mode, bus) as usize;
+        self.write(bus, addr, self.a);
       }
 
       Opcode::STX => {
-        let addr = self.fetch_operand(addr_mode, machine) as usize;
-        machine.write(addr, self.x);
+        let addr = self.fetch_write_operand(addr_mode, bus) as usize;
+        self.write(bus, addr, self.x);
       }
 
       Opcode::STY => {
-        let addr = self.fetch_operand(addr_mode, machine) as usize;
-        machine.write(addr, self.y);
+        let addr = self.fetch_write_operand(addr_mode, bus) as usize;
+        self.write(bus, addr, self.y);
       }
 
       // レジスタ間転送
       Opcode::TAX => {
-        let res = self.a;
-
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.set_z_flag(res == 0);
-        self.x = res;
+        self.x = self.a;
+        self.set_nz_flag(self.x);
       }
 
       Opcode::TXA => {
-        let res = self.x;
-
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.set_z_flag(res == 0);
-        self.a = res;
+        self.a = self.x;
+        self.set_nz_flag(self.a);
       }
 
       Opcode::TAY => {
-        let res = self.a;
-
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.set_z_flag(res == 0);
-        self.y = res;
+        self.y = self.a;
+        self.set_nz_flag(self.y);
       }
 
       Opcode::TYA => {
-        let res = self.y;
-
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.set_z_flag(res == 0);
-        self.a = res;
+        self.a = self.y;
+        self.set_nz_flag(self.a);
       }
 
       Opcode::TSX => {
-        let res = (self.sp & ((1 << 8) - 1)) as u8;
-
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.set_z_flag(res == 0);
-        self.x = res as u8;
+        self.x = self.sp;
+        self.set_nz_flag(self.x);
       }
 
       Opcode::TXS => {
-        self.sp = (self.x as u16) | (1 << 8);
+        // TXS だけはフラグが変わらない
+        self.sp = self.x;
       }
 
       // スタック
       Opcode::PHA => {
-        self.push_stack(machine, self.a);
+        self.push_stack(bus, self.a);
       }
 
       Opcode::PLA => {
-        let res = self.pop_stack(machine);
-
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.set_z_flag(res == 0);
-        self.a = res;
+        self.dummy_read(bus, 0x100 | self.sp as usize);
+        self.a = self.pop_stack(bus);
+        self.set_nz_flag(self.a);
       }
 
       Opcode::PHP => {
-        self.push_stack(machine, self.p);
+        // 積むときは B と bit 5 を立てる
+        self.push_stack(bus, self.p | 0x30);
       }
 
       Opcode::PLP => {
-        self.p = self.pop_stack(machine);
+        // B フラグは無視, bit 5 は常に1
+        self.dummy_read(bus, 0x100 | self.sp as usize);
+        self.p = (self.pop_stack(bus) & !0x10) | 0x20;
       }
 
       // Unofficial opecodes
+      // https://wiki.nesdev.com/w/index.php/Programming_with_unofficial_opcodes
+      // RMW 命令 + 演算
+      Opcode::DCP => {
+        let m = self.modify(addr_mode, bus, |_, m| m.wrapping_sub(1));
+        self.compare(self.a, m);
+      }
+
       Opcode::ISC => {
-        let addr = self.fetch_operand(addr_mode, machine);
-        let val = self.fetch_data(addr_mode, machine) + 1;
-        machine.write(addr as usize, val);
+        let m = self.modify(addr_mode, bus, |_, m| m.wrapping_add(1));
+        self.sbc(m);
+      }
+
+      Opcode::RLA => {
+        let m = self.modify(addr_mode, bus, |cpu, m| {
+          let c = cpu.read_c_flag() as u8;
+          cpu.set_c_flag(m & (1 << 7) == 1 << 7);
+          (m << 1) | c
+        });
+        self.a &= m;
+        self.set_nz_flag(self.a);
+      }
+
+      Opcode::RRA => {
+        let m = self.modify(addr_mode, bus, |cpu, m| {
+          let c = cpu.read_c_flag() as u8;
+          cpu.set_c_flag(m & 1 == 1);
+          (m >> 1) | (c << 7)
+        });
+        self.adc(m);
+      }
+
+      Opcode::SLO => {
+        let m = self.modify(addr_mode, bus, |cpu, m| {
+          cpu.set_c_flag(m & (1 << 7) == 1 << 7);
+          m << 1
+        });
+        self.a |= m;
+        self.set_nz_flag(self.a);
+      }
+
+      Opcode::SRE => {
+        let m = self.modify(addr_mode, bus, |cpu, m| {
+          cpu.set_c_flag(m & 1 == 1);
+          m >> 1
+        });
+        self.a ^= m;
+        self.set_nz_flag(self.a);
+      }
+
+      // 即値との結合演算
+      Opcode::ALR => {
+        // AND + LSR A
+        self.a &= self.fetch_data(addr_mode, bus);
+        self.set_c_flag(self.a & 1 == 1);
+        self.a >>= 1;
+        self.set_nz_flag(self.a);
+      }
+
+      Opcode::ANC => {
+        // AND の結果の bit 7 が C に入る
+        self.a &= self.fetch_data(addr_mode, bus);
+        self.set_nz_flag(self.a);
+        self.set_c_flag(self.read_n_flag());
+      }
+
+      Opcode::ARR => {
+        // AND + ROR A, C は bit 6, V は bit 6 xor bit 5
+        let m = self.a & self.fetch_data(addr_mode, bus);
+        self.a = (m >> 1) | ((self.read_c_flag() as u8) << 7);
+        self.set_nz_flag(self.a);
+        self.set_c_flag(self.a & (1 << 6) == 1 << 6);
+        self.set_v_flag(((self.a >> 6) ^ (self.a >> 5)) & 1 == 1);
+      }
+
+      Opcode::AXS => {
+        // X = (A & X) - M (ボローなしの減算)
+        let m = self.fetch_data(addr_mode, bus);
+        let ax = self.a & self.x;
+        self.set_c_flag(ax >= m);
+        self.x = ax.wrapping_sub(m);
+        self.set_nz_flag(self.x);
+      }
+
+      Opcode::LAX => {
+        let m = if addr_mode == Addressing::Immediate {
+          // 0xab は不安定なので (A | 0xee) & M に固定
+          (self.a | 0xee) & self.fetch_data(addr_mode, bus)
+        } else {
+          self.fetch_data(addr_mode, bus)
+        };
+        self.a = m;
+        self.x = m;
+        self.set_nz_flag(m);
+      }
+
+      Opcode::SAX => {
+        let addr = self.fetch_write_operand(addr_mode, bus) as usize;
+        self.write(bus, addr, self.a & self.x);
+      }
 
-        let (data, over_1) = self.a.overflowing_sub(val);
-        let (res, over_2) = data.overflowing_sub(if self.read_c_flag() == 1 { 0 } else { 1 });
+      // 読むだけの NOP (読み出しの副作用は起きる)
+      Opcode::SKB | Opcode::IGN => {
+        self.fetch_data(addr_mode, bus);
+      }
+
+      // 不安定な命令
+      Opcode::XAA => {
+        let m = self.fetch_data(addr_mode, bus);
+        self.a = (self.a | 0xee) & self.x & m;
+        self.set_nz_flag(self.a);
+      }
+
+      Opcode::AHX => {
+        let val = self.a & self.x;
+        self.store_high_and(addr_mode, bus, self.y, val);
+      }
+
+      Opcode::SHY => {
+        self.store_high_and(addr_mode, bus, self.x, self.y);
+      }
 
-        self.set_n_flag(res & (1 << 7) == (1 << 7));
-        self.set_v_flag(
-          (self.a ^ val) & (1 << 7) == (1 << 7) && (self.a ^ res) & (1 << 7) == (1 << 7),
-        );
-        self.set_z_flag(res == 0);
-        self.set_c_flag(!(over_1 || over_2));
-        self.a = res;
+      Opcode::SHX => {
+        self.store_high_and(addr_mode, bus, self.y, self.x);
+      }
+
+      Opcode::TAS => {
+        self.sp = self.a & self.x;
+        self.store_high_and(addr_mode, bus, self.y, self.sp);
+      }
+
+      Opcode::LAS => {
+        let m = self.fetch_data(addr_mode, bus) & self.sp;
+        self.a = m;
+        self.x = m;
+        self.sp = m;
+        self.set_nz_flag(m);
+      }
+
+      // PC を戻して同じ命令を実行し続ける (リセットするまで止まったまま)
+      Opcode::KIL => {
+        self.pc = self.pc.wrapping_sub(1);
       }
 
       // Do nothing (just like me)
       Opcode::NOP => {}
+    }
 
-      _ => {
-        println!("Unknown instruction");
+    // 読み出し命令はインデックスでページを跨ぐと +1 サイクル
+    let mut cycles = info.cycles as u16 + self.extra_cycles as u16;
+    if info.page_penalty && self.page_crossed {
+      cycles += 1;
+    }
+
+    // OAM DMA の間 CPU は止まる (奇数サイクルから始まると +1)
+    // https://wiki.nesdev.com/w/index.php/PPU_registers#OAMDMA
+    if bus.take_oam_dma() {
+      let stall = 513 + ((self.cycles + cycles as u64) % 2) as u16;
+      if self.cycle_step {
+        for _ in 0..stall {
+          bus.tick();
+        }
+      }
+      cycles += stall;
+    }
+
+    // 最後から2番目のサイクルまでに検出した割り込みを処理する
+    if !self.cycle_step {
+      self.poll_interrupt(bus);
+      // CLI, SEI, PLP で変えた I フラグは次の命令から効く (RTI はすぐ効く)
+      if matches!(opcode, Opcode::CLI | Opcode::SEI | Opcode::PLP) {
+        self.run_irq = bus.irq() && !i_flag;
       }
+      self.prev_need_nmi = self.need_nmi;
+      self.prev_run_irq = self.run_irq;
+    }
+
+    if self.prev_need_nmi {
+      self.interrupt(bus, Interrupt::NMI);
+      cycles += 7;
+    } else if self.prev_run_irq {
+      self.interrupt(bus, Interrupt::IRQ);
+      cycles += 7;
     }
 
-    /*
-      println!(
-        "Stat:
-    \x1b[38;2;252;200;0mAccumulator: {}\x1b[m,
-    \x1b[38;2;20;210;240mFlag: {:<08b}\x1b[m",
-        self.a,
-        self.p
-      );
-      */
-
-    (cycle, code)
+    self.cycles += cycles as u64;
+    (cycles, code)
   }
 }
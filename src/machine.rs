@@ -1,98 +1,253 @@
-const WRAM_SIZE: usize = 0x800; // 2KiB
-const VRAM_SIZE: usize = 0x800; // 2KiB
-const PRG_ROM_SIZE: usize = 0x8000;
-const CHR_ROM_SIZE: usize = 0x2000;
+use super::bus::Bus;
+use super::cartridge::{Cartridge, LoadError};
+use super::mapper;
+use super::ppu;
 
-// 0 = $2000, 1 = $2400, 2 = $2800, 3 = $2c00
-const NAME_TABLE_SIZE: usize = 0x400;
+const WRAM_SIZE: usize = 0x800; // 2KiB
+const IO_REGISTER_SIZE: usize = 0x18; // $4000 ~ $4017
 
-#[derive(Copy, Clone)]
 pub struct Machine {
   pub wram: [u8; WRAM_SIZE],
-  pub vram: [u8; VRAM_SIZE],
 
-  pub prg_rom: [u8; PRG_ROM_SIZE],
-  pub chr_rom: [u8; CHR_ROM_SIZE],
+  // カセット ($4020 ~ $ffff, PPU $0000 ~ $1fff)
+  mapper: Option<Box<dyn mapper::Mapper>>,
 
-  // for PPU
-  pub nametable: [[u8; NAME_TABLE_SIZE]; 4],
-  pub ppu_register: [u8; 8],
+  // PPU ($2000 ~ $3fff, ネームテーブル, パレット)
+  pub ppu: ppu::Ppu,
 
-  pub prg_bytes: usize,
-  pub chr_bytes: usize,
+  // for APU and I/O
+  pub io_register: [u8; IO_REGISTER_SIZE],
 
-  tow_time: bool, // $2006 の2回書き込みの記録
-  upper_bits: u8, // $2006 1回目書き込み保持用
+  oam_dma: bool, // $4014 に書き込まれて DMA が始まったか
+
+  pub cycles: u64, // tick で進んだ CPU サイクル数
 }
 
 impl Machine {
   pub fn new() -> Self {
     Self {
       wram: [0; WRAM_SIZE],
-      vram: [0; VRAM_SIZE],
-      prg_rom: [0; PRG_ROM_SIZE],
-      chr_rom: [0; CHR_ROM_SIZE],
 
-      nametable: [[0; NAME_TABLE_SIZE]; 4],
-      ppu_register: [0; 8],
+      mapper: None,
+
+      ppu: ppu::Ppu::new(),
+
+      io_register: [0; IO_REGISTER_SIZE],
+
+      oam_dma: false,
+
+      cycles: 0,
+    }
+  }
+
+  // カセットを挿す
+  pub fn set_cartridge(&mut self, cartridge: Cartridge) -> Result<(), LoadError> {
+    self.mapper = Some(mapper::new(cartridge)?);
+    Ok(())
+  }
+
+  // バッテリーバックアップされた PRG-RAM (セーブデータ) を持つカセットか
+  pub fn battery(&self) -> bool {
+    match &self.mapper {
+      Some(mapper) => mapper.cartridge().header.battery,
+      None => false,
+    }
+  }
+
+  pub fn prg_ram(&self) -> &[u8] {
+    match &self.mapper {
+      Some(mapper) => &mapper.cartridge().prg_ram,
+      None => &[],
+    }
+  }
+
+  pub fn load_prg_ram(&mut self, data: &[u8]) {
+    if let Some(mapper) = &mut self.mapper {
+      let prg_ram = &mut mapper.cartridge_mut().prg_ram;
+      let len = prg_ram.len().min(data.len());
+      prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+  }
+
+  // 最後に保存してから PRG-RAM が書き換えられたか
+  pub fn prg_ram_dirty(&self) -> bool {
+    match &self.mapper {
+      Some(mapper) => mapper.cartridge().prg_ram_dirty,
+      None => false,
+    }
+  }
+
+  // PRG-RAM を保存したので書き換えの印を下ろす
+  pub fn clear_prg_ram_dirty(&mut self) {
+    if let Some(mapper) = &mut self.mapper {
+      mapper.cartridge_mut().prg_ram_dirty = false;
+    }
+  }
+
+  // PPU と同じようにパターンテーブル ($0000 ~ $1fff) を読む (テスト用)
+  #[cfg(test)]
+  pub fn read_chr(&mut self, addr: usize) -> u8 {
+    match &mut self.mapper {
+      Some(mapper) => {
+        mapper.ppu_address(addr);
+        mapper.read_chr(addr)
+      }
+      None => 0,
+    }
+  }
+
+  // PPU のアドレスを通知せずにパターンテーブルを読む (デバッグ用)
+  pub fn peek_chr(&self, addr: usize) -> u8 {
+    match &self.mapper {
+      Some(mapper) => mapper.peek_chr(addr),
+      None => 0,
+    }
+  }
+
+  // 現在のネームテーブルのミラーリング (テスト用)
+  #[cfg(test)]
+  pub fn mirroring(&self) -> Option<mapper::Mirroring> {
+    self.mapper.as_ref().map(|mapper| mapper.mirroring())
+  }
+
+  // CPU を1サイクル進める (サイクル単位で実行するモードでバスアクセスごとに呼ばれる)
+  // PPU は CPU 1サイクルで 3 ドット進む
+  pub fn tick(&mut self) {
+    self.cycles += 1;
+    for _ in 0..3 {
+      self.ppu.exec(&mut self.mapper);
+    }
+  }
 
-      prg_bytes: 0,
-      chr_bytes: 0,
+  // CPU のバスアクセスをマッパーに通知する
+  fn cpu_cycle(&mut self) {
+    if let Some(mapper) = &mut self.mapper {
+      mapper.cpu_cycle();
+    }
+  }
 
-      tow_time: false,
-      upper_bits: 0,
+  // OAM DMA ($xx00 ~ $xxff を OAMADDR から順に OAM に転送する)
+  // CPU が止まるサイクルは exec が take_oam_dma で数える
+  fn run_oam_dma(&mut self, page: u8) {
+    let base = (page as usize) << 8;
+    for i in 0..0x100 {
+      let data = self.read(base | i);
+      let addr = self.ppu.oam_addr.wrapping_add(i as u8);
+      self.ppu.oam[addr as usize] = data;
     }
+    self.oam_dma = true;
   }
 
-  pub fn set_roms(&mut self, prg_rom: [u8; PRG_ROM_SIZE], chr_rom: [u8; CHR_ROM_SIZE]) {
-    self.prg_rom = prg_rom;
-    self.chr_rom = chr_rom;
+  // 前回の呼び出しから OAM DMA が発生したか
+  pub fn take_oam_dma(&mut self) -> bool {
+    let dma = self.oam_dma;
+    self.oam_dma = false;
+    dma
   }
 
+  // PPU からの NMI (VBlank)
+  pub fn nmi(&self) -> bool {
+    self.ppu.nmi()
+  }
+
+  // カセットからの IRQ
+  pub fn irq(&self) -> bool {
+    match &self.mapper {
+      Some(mapper) => mapper.irq(),
+      None => false,
+    }
+  }
+
+  // CPU のメモリマップ
+  // https://wiki.nesdev.com/w/index.php/CPU_memory_map
+  //
+  // | Address | Size | Description |
+  // | - | - | - |
+  // | $0000 ~ $07ff | $0800 | WRAM |
+  // | $0800 ~ $1fff | - | WRAM のミラー |
+  // | $2000 ~ $2007 | $0008 | PPU レジスタ |
+  // | $2008 ~ $3fff | - | PPU レジスタのミラー |
+  // | $4000 ~ $4017 | $0018 | APU, I/O レジスタ |
+  // | $4018 ~ $401f | $0008 | APU テストモード用 (通常は無効) |
+  // | $4020 ~ $5fff | $1fe0 | 拡張 ROM |
+  // | $6000 ~ $7fff | $2000 | PRG-RAM (拡張 RAM) |
+  // | $8000 ~ $ffff | $8000 | PRG-ROM |
+  //
+  // $4020 以降はカセットのマッパーが処理する
   pub fn write(&mut self, addr: usize, val: u8) {
-    if addr < 0x2000 {
-      self.wram[addr] = val;
-    } else {
-      // println!("ppu reg -> {}", (addr - 0x2000) % 8);
-      println!("ppu addr ${:<04x}", addr - 0x2000);
-
-      // VRAMを操作するための I/O ポート
-      match addr {
-        0x2002 => {
-          // TODO: VBlank か否かのビットを立てる
-        },
-
-        0x2006 => {
-          // TODO: VRAM のアドレスを書き込む
-          // 2回書き込む（1回目上位8bit, 2回目下位8bit)
-          // self.vram[addr - 0x2000] = val;
-          if self.tow_time {
-            // 2回目
-          } else {
-            // 1回目
-            self.upper_bits = val;
-          }
-
-          self.tow_time = !self.tow_time;
-        },
-
-        0x2007 => {
-          // 書き込むことでアクセスを発生させる
-          self.ppu_register[7] = val
-        },
-
-        _ => {
-          self.ppu_register[addr - 0x2000] = val
+    match addr {
+      0x0000..=0x1fff => self.wram[addr % WRAM_SIZE] = val,
+      0x2000..=0x3fff => self.ppu.write_register(addr, val, &mut self.mapper),
+      0x4014 => {
+        self.io_register[0x14] = val;
+        self.run_oam_dma(val);
+      }
+      0x4000..=0x4017 => self.io_register[addr - 0x4000] = val,
+      0x4018..=0x401f => {} // テストモード
+      _ => {
+        if let Some(mapper) = &mut self.mapper {
+          mapper.write(addr, val);
         }
       }
     }
   }
 
   pub fn read(&mut self, addr: usize) -> u8 {
-    if addr >= self.prg_bytes {
-        self.prg_rom[addr - self.prg_bytes]
-    } else {
-        self.prg_rom[addr]
+    match addr {
+      0x0000..=0x1fff => self.wram[addr % WRAM_SIZE],
+      0x2000..=0x3fff => self.ppu.read_register(addr, &mut self.mapper),
+      0x4000..=0x4017 => self.io_register[addr - 0x4000],
+      0x4018..=0x401f => 0,
+      _ => match &mut self.mapper {
+        Some(mapper) => mapper.read(addr),
+        None => 0,
+      },
+    }
+  }
+
+  // read と同じ値を状態を変えずに返す (デバッガ, 逆アセンブラ用)
+  pub fn peek(&self, addr: usize) -> u8 {
+    match addr {
+      0x0000..=0x1fff => self.wram[addr % WRAM_SIZE],
+      0x2000..=0x3fff => self.ppu.peek_register(addr, &self.mapper),
+      0x4000..=0x4017 => self.io_register[addr - 0x4000],
+      0x4018..=0x401f => 0,
+      _ => match &self.mapper {
+        Some(mapper) => mapper.peek(addr),
+        None => 0,
+      },
     }
   }
 }
+
+impl Bus for Machine {
+  fn read(&mut self, addr: usize) -> u8 {
+    self.cpu_cycle();
+    Machine::read(self, addr)
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    self.cpu_cycle();
+    Machine::write(self, addr, val)
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    Machine::peek(self, addr)
+  }
+
+  fn tick(&mut self) {
+    Machine::tick(self)
+  }
+
+  fn nmi(&self) -> bool {
+    Machine::nmi(self)
+  }
+
+  fn irq(&self) -> bool {
+    Machine::irq(self)
+  }
+
+  fn take_oam_dma(&mut self) -> bool {
+    Machine::take_oam_dma(self)
+  }
+}
@@ -6,11 +6,16 @@ extern crate piston_window;
 use image::*;
 use piston_window::*;
 use std::env;
+use std::process;
 use std::time::SystemTime;
 
+mod bus;
+mod cartridge;
 mod cpu;
+mod disassembler;
 mod instruction;
 mod machine;
+mod mapper;
 mod ppu;
 mod system;
 
@@ -21,6 +26,8 @@ const WIDTH: u32 = 256;
 const HEIGHT: u32 = 240;
 const SIZE: f64 = 2.0; // !you must change to 2 if use gdb
 
+const SAVE_INTERVAL: u64 = 60; // セーブデータを書き出す間隔 (フレーム数)
+
 fn main() {
   // デバッグモード判定用
   let args: Vec<String> = env::args().collect();
@@ -36,21 +43,27 @@ fn main() {
   // 初期化する
   let mut machine = machine::Machine::new();
   let mut cpu = cpu::Cpu::new();
-  let mut ppu = ppu::Ppu::new();
 
   // カセット読み込み
-  let path = "./roms/sample1.nes"; // Hello World (未達成)
+  let path = "./roms/sample1.nes"; // Hello World
                                    // let path = "./roms/nestest.nes"; // 色々テストできるROM
                                    // let path = "./roms/SHOOT.nes"; // シューティングゲーム (未達成)
-  let result = system::load_cassette(&mut machine, path.to_string(), cui_debug); // ROMのサイズをmachineに渡す
+  let result = system::load_cassette(path.to_string(), cui_debug);
 
-  let (prg_rom, chr_rom) = match result {
-    Ok(rom) => rom,
-    Err(_) => panic!("Failed to get PRG-ROM or CHR-ROM"),
-  };
+  // machineにカセットをセット
+  if let Err(err) = result.and_then(|cartridge| machine.set_cartridge(cartridge)) {
+    eprintln!("\x1b[38;5;196mFailed to load {}: {}\x1b[m", path, err);
+    process::exit(1);
+  }
+
+  // セーブデータの読み込み
+  let save_path = system::save_path(path);
+  if let Err(err) = system::load_save(&mut machine, &save_path) {
+    eprintln!("\x1b[38;5;196mFailed to load {}: {}\x1b[m", save_path.display(), err);
+  }
 
-  // machineにROMをセット
-  machine.set_roms(prg_rom, chr_rom);
+  // バスアクセスごとに PPU を進める
+  cpu.cycle_step = true;
 
   // 電源が入るとRESETの割込処理が走る
   cpu.interrupt(&mut machine, instruction::Interrupt::RESET);
@@ -75,16 +88,20 @@ fn main() {
   let factory = window.create_texture_context();
   let mut glyphs = Glyphs::new(font, factory, TextureSettings::new()).unwrap(); // thanks, @megumish
 
-  // NESの画面
-  let mut screen = ImageBuffer::new(WIDTH * SIZE as u32, HEIGHT * SIZE as u32);
+  // NESの画面 (描画するときに SIZE 倍する)
+  let mut screen = ImageBuffer::new(WIDTH, HEIGHT);
 
   let mut texture_context = TextureContext {
     factory: window.factory.clone(),
     encoder: window.factory.create_command_buffer().into(),
   };
 
-  let mut texture = Texture::from_image(&mut texture_context, &screen, &TextureSettings::new())
-    .expect("Failed to create texture.");
+  let mut texture = Texture::from_image(
+    &mut texture_context,
+    &screen,
+    &TextureSettings::new().filter(Filter::Nearest),
+  )
+  .expect("Failed to create texture.");
 
   // デバッグ側にCHR-ROMを書き出す画面
   let mut debug_screen = ImageBuffer::new(WIDTH * SIZE as u32 + DEBUG_WIDTH, HEIGHT * SIZE as u32);
@@ -102,28 +119,40 @@ fn main() {
   .expect("Failed to create texture.");
 
   let start_at = SystemTime::now(); // システムの起動時間を計測
-  let timing = (263 * (341 / 3)) as usize; // PPUと同期するために必要
   let mut cpu_count = 0; // 命令の実行数をカウント
   let mut max_pc: u16 = 0; // デバッグ用 pc最大値
+  let mut frames: u64 = 0; // 描画したフレーム数
 
   let mut events = Events::new(EventSettings::new());
   while let Some(e) = events.next(&mut window) {
     if let Some(args) = e.render_args() {
-      // タイミング調整用
-      let mut cycles = 0;
-      while cycles < timing {
+      // PPU が VBlank に入る (1フレーム描き上がる) まで CPU を進める
+      // PPU は CPU のバスアクセスごとに 3 ドット進み, VBlank に入ると NMI (PPUCTRL の bit 7 が立っているとき)
+      while !machine.ppu.take_frame() {
+        // NMI, IRQ は exec の中で処理される
         let exec_res = cpu.exec(&mut machine);
         // println!("inst {:x}", exec_res.1);
         cpu_count += 1;
 
-        cycles += exec_res.0 as usize;
         if max_pc < cpu.pc {
           max_pc = cpu.pc;
-          println!("max pc {:<02}, opecode {:?}", max_pc, cpu.convert(exec_res.1));
+          println!("max pc {:<02}, opecode {:?}", max_pc, instruction::decode(exec_res.1).opcode);
         }
       }
 
-      // PPUでアレコレしてNESの画面を更新
+      // 1秒ごとにセーブデータを書き出す
+      frames += 1;
+      if frames.is_multiple_of(SAVE_INTERVAL) {
+        if let Err(err) = system::write_save(&mut machine, &save_path) {
+          eprintln!("\x1b[38;5;196mFailed to write {}: {}\x1b[m", save_path.display(), err);
+        }
+      }
+
+      // PPU が描画した画面を NESの画面に写す
+      for (i, rgb) in machine.ppu.frame.chunks(3).enumerate() {
+        let (x, y) = ((i % ppu::WIDTH) as u32, (i / ppu::WIDTH) as u32);
+        screen.put_pixel(x, y, Rgba([rgb[0], rgb[1], rgb[2], 255]));
+      }
       texture.update(&mut texture_context, &screen).unwrap();
 
       window.draw_2d(&e, |c, g, d| {
@@ -215,7 +244,8 @@ fn main() {
             .draw(&text, &mut glyphs, &c.draw_state, transform, g)
             .unwrap();
 
-          text = format!("PC: 0x{:<016x}", cpu.pc);
+          // 次に実行する命令
+          text = format!("PC: 0x{:<016x} {}", cpu.pc, disassembler::disassemble_at(&machine, cpu.pc));
           transform = c
             .transform
             .trans(WIDTH as f64 * SIZE + margin_x, base_y + 150.0);
@@ -241,13 +271,13 @@ fn main() {
           // キャッシュクリアしたりいい感じにする
           glyphs.factory.encoder.flush(d);
 
-          // 直接CHR-ROMの中身を全部描画してみる
-          for i in 0..chr_rom.len() / 16
+          // 直接パターンテーブル (CHR-ROM, CHR-RAM) の中身を全部描画してみる
+          for i in 0..0x2000 / 16
           /* (32 * 10) */
           {
             let base = 16 * i; // * (0x21 + i); // $21: 記号と数字, $41: 英大文字と感嘆/疑問符
-            let pattern_low = &chr_rom[base..base + 0x8]; // 0 ~ 7
-            let pattern_high = &chr_rom[base + 0x8..base + 0x10]; // 8 ~ 15
+            let pattern_low: Vec<u8> = (base..base + 0x8).map(|addr| machine.peek_chr(addr)).collect(); // 0 ~ 7
+            let pattern_high: Vec<u8> = (base + 0x8..base + 0x10).map(|addr| machine.peek_chr(addr)).collect(); // 8 ~ 15
 
             for y in 0..8 {
               for x in 0..8 {
@@ -283,6 +313,11 @@ fn main() {
       });
     }
   }
+
+  // 終了時にもセーブデータを書き出す
+  if let Err(err) = system::write_save(&mut machine, &save_path) {
+    eprintln!("\x1b[38;5;196mFailed to write {}: {}\x1b[m", save_path.display(), err);
+  }
 }
 
 // テストクン
@@ -300,8 +335,7 @@ fn cpu_register() {
 #[test]
 fn load_cassette() {
   let path = "./roms/sample1.nes".to_string();
-  let mut machine = machine::Machine::new();
-  let result = system::load_cassette(&mut machine, path, false);
+  let result = system::load_cassette(path, false);
 
   match result {
     Ok(_) => (),
@@ -314,14 +348,13 @@ fn check_rom_data() {
   let prg_data = [0x78, 0xa2, 0xff, 0x9a, 0xa9, 0x00, 0x8d, 0x00, 0x20, 0x8d]; // from 0
   let chr_data = [0x1c, 0x3e, 0x3e, 0x3e, 0x1c, 0x1c, 0x1c, 0x1c, 0x18, 0x3c]; // from 528
   let path = "./roms/sample1.nes".to_string();
-  let mut machine = machine::Machine::new();
-  let result = system::load_cassette(&mut machine, path, false);
+  let result = system::load_cassette(path, false);
 
   for id in 0..10 {
     match &result {
-      Ok((prg_rom, chr_rom)) => {
-        assert_eq!(prg_rom[id], prg_data[id]);
-        assert_eq!(chr_rom[id + 528], chr_data[id]);
+      Ok(cartridge) => {
+        assert_eq!(cartridge.prg_rom[id], prg_data[id]);
+        assert_eq!(cartridge.chr_rom[id + 528], chr_data[id]);
       }
 
       _ => {}
@@ -332,21 +365,1122 @@ fn check_rom_data() {
 #[test]
 fn transfer_bytes() {
   let path = "./roms/sample1.nes".to_string();
-  let mut machine = machine::Machine::new();
-  system::load_cassette(&mut machine, path, false);
-  assert_eq!(machine.prg_bytes, 32768); // PRG_ROM size of Hello World
-  assert_eq!(machine.chr_bytes, 8192); // CHR_ROM size of Hello World
+  let cartridge = system::load_cassette(path, false).unwrap();
+  assert_eq!(cartridge.prg_rom.len(), 32768); // PRG_ROM size of Hello World
+  assert_eq!(cartridge.chr_rom.len(), 8192); // CHR_ROM size of Hello World
 }
 
-/*
 #[test]
 fn stack_and_pop() {
-  let addr = 0x1fe;
   let mut cpu = cpu::Cpu::new();
   let mut machine = machine::Machine::new();
 
   assert_eq!(cpu.pop_stack(&mut machine), 0); // sp がインクリメントされる
   cpu.push_stack(&mut machine, 0x7b);
+  assert_eq!(machine.read(0x1ff), 0x7b); // スタックは $0100 ~ $01ff
   assert_eq!(cpu.pop_stack(&mut machine), 0x7b); // インクリメントされた分ずらす
+
+  // $0100 の次は $01ff に戻る
+  cpu.sp = 0x00;
+  cpu.push_stack(&mut machine, 0x12);
+  assert_eq!(cpu.sp, 0xff);
+  assert_eq!(machine.read(0x100), 0x12);
+  assert_eq!(cpu.pop_stack(&mut machine), 0x12);
+
+  // 16bit の値は上位バイトから積む
+  cpu.sp = 0xfd;
+  cpu.push_stack_16(&mut machine, 0xc123);
+  assert_eq!(machine.read(0x1fd), 0xc1);
+  assert_eq!(machine.read(0x1fc), 0x23);
+  assert_eq!(cpu.pop_stack_16(&mut machine), 0xc123);
+  assert_eq!(cpu.sp, 0xfd);
+}
+#[test]
+fn nestest() {
+  // nestest の自動実行モード ($c000 から開始)
+  // https://www.qmtpro.com/~nes/misc/nestest.txt
+  let path = "./roms/nestest.nes".to_string();
+  let mut machine = machine::Machine::new();
+  let cartridge = system::load_cassette(path, false).unwrap();
+  machine.set_cartridge(cartridge).unwrap();
+
+  let mut cpu = cpu::Cpu::new();
+  cpu.pc = 0xc000;
+  cpu.sp = 0xfd;
+  cpu.p = 0x24;
+
+  // 公式命令のテストが終わり, エラーコードが $10 に退避されるまで進める
+  let mut steps = 0;
+  while cpu.pc != 0xc623 {
+    cpu.exec(&mut machine);
+    steps += 1;
+    assert!(steps < 100_000, "nestest did not finish (pc = ${:04x})", cpu.pc);
+  }
+
+  assert_eq!(machine.read(0x10), 0x00);
+  assert_eq!(cpu.sp, 0xfd);
+
+  // 非公式命令を含む残りのテストを最後の RTS ($c66e) まで進める
+  while cpu.pc != 0xc66e {
+    cpu.exec(&mut machine);
+    steps += 1;
+    assert!(steps < 100_000, "nestest did not finish (pc = ${:04x})", cpu.pc);
+  }
+
+  // nestest.log の最終行は CYC:26554 (リセット直後の 7 サイクルから数えている)
+  assert_eq!(cpu.cycles, 26554 - 7);
+
+  // 失敗したテストがあれば $02, $03 にエラーコードが入る
+  assert_eq!(machine.read(0x02), 0x00);
+  assert_eq!(machine.read(0x03), 0x00);
+  assert_eq!(machine.read(0x11), 0x00);
+  assert_eq!(machine.read(0x00), 0x00);
+}
+
+#[test]
+fn unofficial_opcodes() {
+  let mut cpu = cpu::Cpu::new();
+  let mut machine = machine::Machine::new();
+
+  // LAX $10; DCP $11; AXS #$01; SLO $12; KIL
+  let program = [0xa7, 0x10, 0xc7, 0x11, 0xcb, 0x01, 0x07, 0x12, 0x02];
+  for (i, code) in program.iter().enumerate() {
+    machine.write(0x0200 + i, *code);
+  }
+  machine.write(0x10, 0x8f);
+  machine.write(0x11, 0x90);
+  machine.write(0x12, 0x81);
+  cpu.pc = 0x0200;
+
+  cpu.exec(&mut machine);
+  assert_eq!((cpu.a, cpu.x), (0x8f, 0x8f));
+
+  // $11 = $8f, A と等しいので Z, C が立つ
+  cpu.exec(&mut machine);
+  assert_eq!(machine.read(0x11), 0x8f);
+  assert_eq!(cpu.p & 0x03, 0x03);
+
+  // X = (A & X) - 1
+  cpu.exec(&mut machine);
+  assert_eq!(cpu.x, 0x8e);
+
+  // $12 = $02, A = $8f | $02
+  cpu.exec(&mut machine);
+  assert_eq!(machine.read(0x12), 0x02);
+  assert_eq!(cpu.a, 0x8f);
+  assert_eq!(cpu.p & 0x01, 0x01);
+
+  // KIL は同じ場所で止まり続ける
+  cpu.exec(&mut machine);
+  cpu.exec(&mut machine);
+  assert_eq!(cpu.pc, 0x0208);
+}
+
+#[test]
+fn cycle_timing() {
+  let mut cpu = cpu::Cpu::new();
+  let mut machine = machine::Machine::new();
+
+  // LDA $01ff,X; LDA $0100,X; BNE +0; BEQ +0; STA $4014; BNE -128 (ページを跨ぐ)
+  let program = [
+    0xbd, 0xff, 0x01, 0xbd, 0x00, 0x01, 0xd0, 0x00, 0xf0, 0x00, 0x8d, 0x14, 0x40, 0xd0, 0x80,
+  ];
+  for (i, code) in program.iter().enumerate() {
+    machine.write(0x0200 + i, *code);
+  }
+  machine.write(0x0101, 0x01);
+  cpu.pc = 0x0200;
+  cpu.x = 0x01;
+
+  assert_eq!(cpu.exec(&mut machine).0, 5); // ページを跨ぐ
+  assert_eq!(cpu.exec(&mut machine).0, 4);
+  assert_eq!(cpu.exec(&mut machine).0, 3); // 分岐する
+  assert_eq!(cpu.exec(&mut machine).0, 2); // 分岐しない
+
+  // OAM DMA の間は止まる (偶数サイクル目から始まるので 513)
+  assert_eq!(cpu.cycles, 14);
+  assert_eq!(cpu.exec(&mut machine).0, 4 + 513);
+
+  // 分岐先が別のページ
+  assert_eq!(cpu.exec(&mut machine).0, 4);
+  assert_eq!(cpu.pc, 0x018f);
+}
+
+#[test]
+fn cycle_step() {
+  // サイクル単位で実行しても nestest の結果とサイクル数は変わらない
+  let path = "./roms/nestest.nes".to_string();
+  let mut machine = machine::Machine::new();
+  let cartridge = system::load_cassette(path, false).unwrap();
+  machine.set_cartridge(cartridge).unwrap();
+
+  let mut cpu = cpu::Cpu::new();
+  cpu.pc = 0xc000;
+  cpu.sp = 0xfd;
+  cpu.p = 0x24;
+  cpu.cycle_step = true;
+
+  while cpu.pc != 0xc66e {
+    let (cycles, code) = cpu.exec(&mut machine);
+    // バスアクセスの回数が命令のサイクル数と一致する
+    assert_eq!(machine.cycles, cpu.cycles, "opcode ${:02x}, {} cycles", code, cycles);
+  }
+  assert_eq!(machine.read(0x02), 0x00);
+  assert_eq!(machine.read(0x03), 0x00);
+}
+
+#[test]
+fn interrupts() {
+  // NOP で埋めた PRG-ROM, ベクタは NMI = $8100, RESET = $8000, IRQ/BRK = $8200
+  let mut prg_rom = vec![0xea; 0x4000];
+  prg_rom[0x3ffa..].copy_from_slice(&[0x00, 0x81, 0x00, 0x80, 0x00, 0x82]);
+  prg_rom[0x0010] = 0x00; // BRK
+  let buffer = ines(0, 0x00, prg_rom, vec![0; 0x2000]);
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+
+  // RESET はスタックに書き込まずに SP を3つ減らす
+  let mut cpu = cpu::Cpu::new();
+  cpu.sp = 0x00;
+  cpu.interrupt(&mut machine, instruction::Interrupt::RESET);
+  assert_eq!(cpu.pc, 0x8000);
+  assert_eq!(cpu.sp, 0xfd);
+  assert_eq!(cpu.p & 0x04, 0x04);
+
+  // NMI は I フラグに関係なく, 信号の立ち上がりで1回だけ起きる
+  machine.write(0x2000, 0x80);
+  machine.ppu.start_vblank();
+  assert_eq!(cpu.exec(&mut machine).0, 2 + 7);
+  assert_eq!(cpu.pc, 0x8100);
+  assert_eq!(cpu.pop_stack(&mut machine), 0x24); // B は積まない
+  assert_eq!(cpu.pop_stack_16(&mut machine), 0x8001);
+  cpu.exec(&mut machine);
+  assert_eq!(cpu.pc, 0x8101);
+
+  // BRK は I フラグが立っていても割り込む
+  machine.ppu.end_vblank();
+  cpu.pc = 0x8010;
+  cpu.sp = 0xfd;
+  assert_eq!(cpu.exec(&mut machine).0, 7);
+  assert_eq!(cpu.pc, 0x8200);
+  assert_eq!(cpu.pop_stack(&mut machine), 0x34); // B を積む
+  assert_eq!(cpu.pop_stack_16(&mut machine), 0x8012);
+
+  // BRK の途中で NMI が来ると NMI のベクタに乗っ取られる
+  cpu.cycle_step = true;
+  cpu.pc = 0x8010;
+  cpu.sp = 0xfd;
+  machine.ppu.start_vblank();
+  cpu.exec(&mut machine);
+  assert_eq!(cpu.pc, 0x8100);
+  assert_eq!(cpu.pop_stack(&mut machine), 0x34);
+}
+
+#[test]
+fn ppu_registers() {
+  let mut prg_rom = vec![0; 0x4000];
+  prg_rom[0] = 0x00;
+  let chr_rom = (0..0x2000).map(|addr| (addr % 0x100) as u8).collect();
+  let buffer = ines(0, 0x00, prg_rom, chr_rom);
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+
+  // PPUSCROLL と PPUADDR は書き込みトグルを共有する
+  machine.write(0x2005, 0x7d); // X = 125
+  machine.write(0x2005, 0x5e); // Y = 94
+  assert_eq!(machine.ppu.t, 0x616f);
+  assert_eq!(machine.ppu.x, 0x05);
+  machine.write(0x2006, 0x3d);
+  machine.read(0x2002); // トグルが戻る
+  machine.write(0x2006, 0x01);
+  machine.write(0x2006, 0x23);
+  assert_eq!(machine.ppu.v, 0x0123);
+
+  // PPUDATA の読み出しは1回遅れる
+  machine.read(0x2007);
+  assert_eq!(machine.read(0x2007), 0x23);
+  assert_eq!(machine.peek(0x2007), 0x24);
+  assert_eq!(machine.read(0x2007), 0x24);
+
+  // PPUCTRL の bit 2 が立っていたら 32 ずつ進む
+  machine.write(0x2000, 0x04);
+  machine.read(0x2007);
+  assert_eq!(machine.ppu.v, 0x0126 + 32);
+
+  // OAMDATA は書き込むとアドレスが進む
+  machine.write(0x2003, 0x10);
+  machine.write(0x2004, 0xaa);
+  machine.write(0x2004, 0xbb);
+  assert_eq!(machine.ppu.oam[0x10..0x12], [0xaa, 0xbb]);
+  machine.write(0x2003, 0x11);
+  assert_eq!(machine.read(0x2004), 0xbb);
+
+  // OAM DMA は OAMADDR から書き込み, OAMADDR は変わらない
+  for i in 0..0x100 {
+    machine.write(0x0300 + i, i as u8);
+  }
+  machine.write(0x2003, 0xfe);
+  machine.write(0x4014, 0x03);
+  assert_eq!(machine.ppu.oam[0xfe..], [0x00, 0x01]);
+  assert_eq!(machine.ppu.oam[0x00], 0x02);
+  assert_eq!(machine.ppu.oam_addr, 0xfe);
+  assert!(machine.take_oam_dma());
+  machine.write(0x4014, 0x40); // $4000 ~ $40ff を読んでも DMA は再び始まらない
+  assert!(machine.take_oam_dma());
+  assert!(!machine.take_oam_dma());
+
+  // PPUSTATUS を読むと VBlank が下りる (peek では下りない)
+  machine.ppu.start_vblank();
+  assert_eq!(machine.peek(0x2002) & 0x80, 0x80);
+  assert_eq!(machine.read(0x200a) & 0x80, 0x80); // $2008 ~ $3fff はミラー
+  assert_eq!(machine.read(0x2002) & 0x80, 0x00);
+}
+
+#[test]
+fn ppu_vram() {
+  // PPUADDR に書き込んで PPUDATA を読む
+  fn read_vram(machine: &mut machine::Machine, addr: u16) -> u8 {
+    machine.write(0x2006, (addr >> 8) as u8);
+    machine.write(0x2006, addr as u8);
+    machine.read(0x2007);
+    machine.read(0x2007)
+  }
+
+  // 水平ミラー: $2000 = $2400, $2800 = $2c00
+  let mut machine = machine::Machine::new();
+  let buffer = ines(0, 0x00, vec![0; 0x4000], vec![0; 0x2000]);
+  machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+  write_vram(&mut machine, 0x2005, 0x11);
+  write_vram(&mut machine, 0x2805, 0x22);
+  assert_eq!(read_vram(&mut machine, 0x2405), 0x11);
+  assert_eq!(read_vram(&mut machine, 0x2c05), 0x22);
+  assert_eq!(read_vram(&mut machine, 0x3005), 0x11); // $3000 ~ $3eff は $2000 ~ $2eff のミラー
+
+  // 垂直ミラー: $2000 = $2800, $2400 = $2c00
+  let mut machine = machine::Machine::new();
+  let buffer = ines(0, 0x01, vec![0; 0x4000], vec![0; 0x2000]);
+  machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+  write_vram(&mut machine, 0x2005, 0x11);
+  write_vram(&mut machine, 0x2405, 0x22);
+  assert_eq!(read_vram(&mut machine, 0x2805), 0x11);
+  assert_eq!(read_vram(&mut machine, 0x2c05), 0x22);
+  assert_eq!(machine.ppu.nametable[0][5], 0x11);
+  assert_eq!(machine.ppu.nametable[1][5], 0x22);
+
+  // 4画面: それぞれ別のネームテーブル
+  let mut machine = machine::Machine::new();
+  let buffer = ines(0, 0x08, vec![0; 0x4000], vec![0; 0x2000]);
+  machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+  for (i, addr) in [0x2000, 0x2400, 0x2800, 0x2c00].iter().enumerate() {
+    write_vram(&mut machine, *addr, i as u8 + 1);
+  }
+  assert_eq!(machine.ppu.nametable.iter().map(|table| table[0]).collect::<Vec<_>>(), [1, 2, 3, 4]);
+
+  // パレット: $3f10 は $3f00 と共有, $3f20 以降はミラー
+  write_vram(&mut machine, 0x3f10, 0x0f);
+  write_vram(&mut machine, 0x3f11, 0x30);
+  assert_eq!(machine.ppu.palette[0x00], 0x0f);
+  assert_eq!(machine.ppu.palette[0x11], 0x30);
+  machine.write(0x2006, 0x3f);
+  machine.write(0x2006, 0x20);
+  assert_eq!(machine.read(0x2007), 0x0f);
+
+  // パレットの読み出しはバッファを経由しない (バッファには下のネームテーブルが入る)
+  write_vram(&mut machine, 0x2f11, 0x55);
+  machine.write(0x2006, 0x3f);
+  machine.write(0x2006, 0x11);
+  assert_eq!(machine.peek(0x2007), 0x30);
+  assert_eq!(machine.read(0x2007), 0x30);
+  machine.write(0x2006, 0x00);
+  machine.write(0x2006, 0x00);
+  assert_eq!(machine.read(0x2007), 0x55);
+}
+
+#[test]
+fn background_rendering() {
+  let path = "./roms/sample1.nes".to_string();
+  let mut machine = machine::Machine::new();
+  let cartridge = system::load_cassette(path, false).unwrap();
+  machine.set_cartridge(cartridge).unwrap();
+  let mut cpu = cpu::Cpu::new();
+  cpu.cycle_step = true;
+  cpu.interrupt(&mut machine, instruction::Interrupt::RESET);
+
+  for _ in 0..10 {
+    while !machine.ppu.take_frame() {
+      cpu.exec(&mut machine);
+    }
+  }
+
+  // 背景色 ($0f = 黒) の中, 15 行目のタイル (y = 112 ~ 119) に白で "HELLO, WORLD!" が出る
+  let lit = |x: usize, y: usize| pixel(&machine.ppu, x, y) != [0x00, 0x00, 0x00];
+  assert_eq!(pixel(&machine.ppu, 0, 0), [0x00, 0x00, 0x00]);
+  for y in 0..ppu::HEIGHT {
+    let text_line = (112..120).contains(&y);
+    assert_eq!((0..ppu::WIDTH).any(|x| lit(x, y)), text_line, "y = {}", y);
+  }
+  assert!((0..72).all(|x| !lit(x, 112)));
+  assert_eq!(pixel(&machine.ppu, 73, 112), [0xff, 0xfe, 0xff]); // H の左上
+  assert!((176..ppu::WIDTH).all(|x| (112..120).all(|y| !lit(x, y))));
+}
+
+#[test]
+fn sprite_rendering() {
+  // タイル 1, 3 は全面が色 1, タイル 2 は左端の列だけ色 1
+  let mut chr_rom = vec![0; 0x2000];
+  for table in [0x0000, 0x1000].iter() {
+    for row in 0..8 {
+      chr_rom[table + 0x10 + row] = 0xff;
+      chr_rom[table + 0x20 + row] = 0x80;
+      chr_rom[table + 0x30 + row] = 0xff;
+    }
+  }
+  let buffer = ines(0, 0x00, vec![0; 0x4000], chr_rom);
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+
+  write_vram(&mut machine, 0x3f00, 0x0f); // 黒
+  write_vram(&mut machine, 0x3f01, 0x30); // 白
+  write_vram(&mut machine, 0x3f11, 0x16); // 赤
+  write_vram(&mut machine, 0x3f15, 0x1a); // 緑
+  write_vram(&mut machine, 0x2000 + 32 + 3, 0x01); // x = 24 ~ 31, y = 8 ~ 15
+  write_vram(&mut machine, 0x2000 + 7 * 32 + 25, 0x01); // x = 200 ~ 207, y = 56 ~ 63
+
+  // $0200 に OAM の内容を置いて DMA で転送する
+  let mut oam = vec![0xff; 0x100];
+  oam[0..4].copy_from_slice(&[9, 0x02, 0x40, 20]); // 左右反転して右端の列だけ
+  oam[4..8].copy_from_slice(&[9, 0x01, 0x01, 24]); // パレット 1
+  for n in 2..11 {
+    oam[n * 4..n * 4 + 4].copy_from_slice(&[39, 0x01, 0x00, 100 + (n as u8 - 2) * 8]);
+  }
+  oam[44..48].copy_from_slice(&[59, 0x01, 0x20, 204]); // 背景の後ろ
+  for (i, val) in oam.iter().enumerate() {
+    machine.write(0x0200 + i, *val);
+  }
+  machine.write(0x4014, 0x02);
+  assert_eq!(machine.ppu.oam[..8], [9, 0x02, 0x40, 20, 9, 0x01, 0x01, 24]);
+
+  // プリレンダーラインを通ってから1フレーム描画する
+  let render = |machine: &mut machine::Machine| {
+    machine.write(0x2005, 0);
+    machine.write(0x2005, 0);
+    for _ in 0..2 {
+      while !machine.ppu.take_frame() {
+        machine.tick();
+      }
+    }
+  };
+  let [black, white, red, green] = [0x0f, 0x30, 0x16, 0x1a].map(|color| ppu::COLORS[color].to_vec());
+
+  machine.write(0x2000, 0x00);
+  machine.write(0x2001, 0x1e);
+  render(&mut machine);
+
+  // OAM の Y 座標 + 1 のラインから表示される
+  assert_eq!(pixel(&machine.ppu, 27, 9), white);
+  assert_eq!(pixel(&machine.ppu, 27, 10), red);
+  assert_eq!(pixel(&machine.ppu, 20, 10), black);
+  assert_eq!(pixel(&machine.ppu, 27, 17), red);
+  assert_eq!(pixel(&machine.ppu, 27, 18), black);
+
+  // 先頭に近いスプライトが手前, 透明なところは後ろのスプライトが見える
+  assert_eq!(pixel(&machine.ppu, 24, 10), green);
+  assert_eq!(pixel(&machine.ppu, 31, 10), green);
+  assert_eq!(pixel(&machine.ppu, 32, 10), black);
+  assert_ne!(machine.ppu.status & 0x40, 0); // スプライト 0 ヒット
+
+  // 1ラインに 8 個まで
+  assert_eq!(pixel(&machine.ppu, 100 + 7 * 8, 40), red);
+  assert_eq!(pixel(&machine.ppu, 100 + 8 * 8, 40), black);
+  assert_ne!(machine.ppu.status & 0x20, 0);
+
+  // 背景の後ろ
+  assert_eq!(pixel(&machine.ppu, 204, 60), white);
+  assert_eq!(pixel(&machine.ppu, 208, 60), red);
+
+  // 8x16: タイル番号の bit 0 で $1000 を選び, 上下2タイルを使う
+  machine.write(0x2003, 0x00);
+  for val in [99, 0x03, 0x00, 50, 99, 0x03, 0x80, 70].iter() {
+    machine.write(0x2004, *val);
+  }
+  machine.write(0x2000, 0x20);
+  render(&mut machine);
+  assert_eq!(pixel(&machine.ppu, 51, 100), black);
+  assert_eq!(pixel(&machine.ppu, 51, 108), red);
+  assert_eq!(pixel(&machine.ppu, 71, 100), red); // 上下反転
+  assert_eq!(pixel(&machine.ppu, 71, 108), black);
+
+  // プリレンダーラインでフラグが下りる
+  machine.write(0x2001, 0x00);
+  while machine.ppu.line != 261 || machine.ppu.dot < 2 {
+    machine.tick();
+  }
+  assert_eq!(machine.ppu.status & 0xe0, 0);
+}
+
+#[test]
+fn ppu_timing() {
+  let mut ppu = ppu::Ppu::new();
+  let mut mapper = None;
+
+  // 241 ラインのドット 1 で VBlank に入り, NMI が有効なら NMI を出す
+  ppu.ctrl = 0x80;
+  let mut dots = 0;
+  while !ppu.take_frame() {
+    assert!(!ppu.nmi());
+    ppu.exec(&mut mapper);
+    dots += 1;
+  }
+  assert_eq!(dots, 241 * ppu::DOTS + 2);
+  assert!(ppu.nmi());
+
+  // プリレンダーラインのドット 1 で VBlank を抜ける
+  while (ppu.line, ppu.dot) != (261, 1) {
+    ppu.exec(&mut mapper);
+  }
+  assert!(ppu.nmi());
+  ppu.exec(&mut mapper);
+  assert!(!ppu.nmi());
+
+  // 描画が無効ならフレームの長さは一定
+  let mut frame = |ppu: &mut ppu::Ppu| {
+    let mut dots = 1;
+    ppu.exec(&mut mapper);
+    while !ppu.take_frame() {
+      ppu.exec(&mut mapper);
+      dots += 1;
+    }
+    dots
+  };
+  frame(&mut ppu);
+  assert_eq!(frame(&mut ppu), ppu::DOTS * ppu::LINES);
+  assert_eq!(frame(&mut ppu), ppu::DOTS * ppu::LINES);
+
+  // 描画が有効なら奇数フレームは 1 ドット短い
+  ppu.mask = 0x08;
+  let lengths = [frame(&mut ppu), frame(&mut ppu)];
+  assert!(lengths.contains(&(ppu::DOTS * ppu::LINES)));
+  assert!(lengths.contains(&(ppu::DOTS * ppu::LINES - 1)));
+
+  // ライン途中で PPUMASK を書き換えると, そのドットから反映される (グレースケール)
+  let mut ppu = ppu::Ppu::new();
+  ppu.palette[0] = 0x16;
+  while (ppu.line, ppu.dot) != (10, 129) {
+    ppu.exec(&mut mapper);
+  }
+  ppu.mask = 0x01;
+  while ppu.line != 11 {
+    ppu.exec(&mut mapper);
+  }
+  assert_eq!(pixel(&ppu, 127, 10), ppu::COLORS[0x16]);
+  assert_eq!(pixel(&ppu, 128, 10), ppu::COLORS[0x10]);
+
+  // CPU 1サイクルで PPU は 3 ドット進む
+  let mut machine = machine::Machine::new();
+  machine.tick();
+  machine.tick();
+  assert_eq!((machine.ppu.line, machine.ppu.dot), (0, 6));
+}
+
+#[test]
+fn opcode_table() {
+  // 公式の命令は 151 個
+  let official = instruction::OPCODES.iter().filter(|info| info.official).count();
+  assert_eq!(official, 151);
+
+  let info = instruction::decode(0xbd);
+  assert_eq!(info.opcode, instruction::Opcode::LDA);
+  assert_eq!(info.mode, instruction::Addressing::AbsoluteX);
+  assert_eq!((info.cycles, info.bytes), (4, 3));
+  assert!(info.page_penalty);
+  assert!(!instruction::decode(0x9d).page_penalty); // STA は常に 5 cycle
+  assert!(!instruction::decode(0xeb).official); // SBC の別名
+
+  // nestest の先頭
+  assert_eq!(disassembler::disassemble(&[0x4c, 0xf5, 0xc5], 0xc000), "JMP $c5f5");
+  assert_eq!(disassembler::disassemble(&[0xd0, 0xfe], 0xc010), "BNE $c010");
+  assert_eq!(disassembler::disassemble(&[0xb1, 0x89], 0x0000), "LDA ($89),Y");
+  assert_eq!(disassembler::disassemble(&[0x6c, 0xff, 0x02], 0x0000), "JMP ($02ff)");
+  assert_eq!(disassembler::disassemble(&[0x4a], 0x0000), "LSR A");
+  assert_eq!(disassembler::disassemble(&[0xa7, 0x10], 0x0000), "*LAX $10");
+}
+
+#[test]
+fn decimal_mode() {
+  // SED; CLC; LDA #$58; ADC #$46; ADC #$01; SEC; LDA #$00; SBC #$01; SBC #$01
+  let program = [
+    0xf8, 0x18, 0xa9, 0x58, 0x69, 0x46, 0x69, 0x01, 0x38, 0xa9, 0x00, 0xe9, 0x01, 0xe9, 0x01,
+  ];
+  let run = |variant| {
+    let mut cpu = cpu::Cpu::new();
+    let mut machine = machine::Machine::new();
+    for (i, code) in program.iter().enumerate() {
+      machine.write(0x0200 + i, *code);
+    }
+    cpu.pc = 0x0200;
+    cpu.variant = variant;
+
+    let mut results = vec![];
+    for _ in 0..9 {
+      cpu.exec(&mut machine);
+      results.push((cpu.a, cpu.p & 0x01));
+    }
+    results
+  };
+
+  // 58 + 46 = 104, 04 + 01 + 1 = 06, 00 - 01 = 99, 99 - 01 - 1 = 97
+  let results = run(cpu::Variant::Mos6502);
+  assert_eq!(results[3], (0x04, 1));
+  assert_eq!(results[4], (0x06, 0));
+  assert_eq!(results[7], (0x99, 0));
+  assert_eq!(results[8], (0x97, 1));
+
+  // NES では D フラグを立てても2進数で計算する
+  let results = run(cpu::Variant::Ricoh2A03);
+  assert_eq!(results[3], (0x9e, 0));
+  assert_eq!(results[7], (0xff, 0));
+}
+
+#[test]
+fn ram_bus() {
+  // PPU などを繋がずに CPU だけを動かす
+  let mut cpu = cpu::Cpu::new();
+  let mut bus = bus::RamBus::new();
+
+  // 10 + 9 + ... + 1 を $8000 に書き込んで, CLI して無限ループ
+  bus.load(
+    0x0400,
+    &[
+      0xa2, 0x0a, 0xa9, 0x00, 0x18, 0x86, 0x00, 0x65, 0x00, 0xca, 0xd0, 0xf9, 0x8d, 0x00, 0x80,
+      0x58, 0x4c, 0x10, 0x04,
+    ],
+  );
+  bus.load(0xfffe, &[0x00, 0x05]);
+  cpu.pc = 0x0400;
+  cpu.p = 0x24;
+  cpu.cycle_step = true;
+
+  while cpu.pc != 0x0410 {
+    cpu.exec(&mut bus);
+  }
+  assert_eq!(bus::Bus::peek(&bus, 0x8000), 55);
+  assert_eq!(disassembler::disassemble_at(&bus, 0x0400), "LDX #$0a");
+  assert_eq!(bus.cycles, cpu.cycles);
+
+  // IRQ は信号が立っている間, I フラグが下りていれば起きる
+  bus.irq = true;
+  cpu.exec(&mut bus);
+  assert_eq!(cpu.pc, 0x0500);
+}
+
+#[test]
+fn irq_latency() {
+  // CLI, NOP, SEI, NOP
+  let mut bus = bus::RamBus::new();
+  bus.load(0x0400, &[0x58, 0xea, 0x78, 0xea]);
+  bus.load(0xfffe, &[0x00, 0x05]);
+  bus.irq = true;
+
+  // CLI の直後はまだ IRQ が起きず, 次の命令の後に起きる
+  let mut cpu = cpu::Cpu::new();
+  cpu.pc = 0x0400;
+  cpu.p = 0x24;
+  cpu.exec(&mut bus);
+  assert_eq!(cpu.pc, 0x0401);
+  cpu.exec(&mut bus);
+  assert_eq!(cpu.pc, 0x0500);
+
+  // SEI の直後はまだ IRQ が起きる
+  cpu.pc = 0x0402;
+  cpu.p = 0x20;
+  cpu.exec(&mut bus);
+  assert_eq!(cpu.pc, 0x0500);
+  assert_eq!(cpu.pop_stack(&mut bus), 0x24);
+  assert_eq!(cpu.pop_stack_16(&mut bus), 0x0403);
+}
+
+#[test]
+fn addressing_modes() {
+  // $0400 に置いた命令を1つ実行する
+  fn run(code: &[u8], x: u8, y: u8, memory: &[(usize, u8)]) -> cpu::Cpu {
+    let mut cpu = cpu::Cpu::new();
+    let mut bus = bus::RamBus::new();
+    bus.load(0x0400, code);
+    for (addr, val) in memory {
+      bus.load(*addr, &[*val]);
+    }
+    cpu.pc = 0x0400;
+    cpu.x = x;
+    cpu.y = y;
+    cpu.exec(&mut bus);
+    cpu
+  }
+
+  // Immediate, Zeropage, Absolute
+  assert_eq!(run(&[0xa9, 0x12], 0, 0, &[]).a, 0x12);
+  assert_eq!(run(&[0xa5, 0x10], 0, 0, &[(0x10, 0x34)]).a, 0x34);
+  assert_eq!(run(&[0xad, 0x34, 0x12], 0, 0, &[(0x1234, 0x56)]).a, 0x56);
+
+  // ZeropageX, ZeropageY はゼロページ内で一周する
+  assert_eq!(run(&[0xb5, 0x80], 0xff, 0, &[(0x7f, 0x01)]).a, 0x01);
+  assert_eq!(run(&[0xb6, 0x80], 0, 0xff, &[(0x7f, 0x02)]).x, 0x02);
+
+  // AbsoluteX, AbsoluteY は $ffff の次が $0000
+  assert_eq!(run(&[0xbd, 0xff, 0xff], 0x02, 0, &[(0x0001, 0x03)]).a, 0x03);
+  assert_eq!(run(&[0xb9, 0xf0, 0x12], 0, 0x20, &[(0x1310, 0x04)]).a, 0x04);
+
+  // IndirectX はポインタの位置もポインタの上位バイトもゼロページ内で一周する
+  assert_eq!(
+    run(&[0xa1, 0x80], 0xff, 0, &[(0x7f, 0x00), (0x80, 0x03), (0x0300, 0x05)]).a,
+    0x05
+  );
+  assert_eq!(
+    run(&[0xa1, 0xff], 0x00, 0, &[(0xff, 0x10), (0x00, 0x03), (0x0310, 0x06)]).a,
+    0x06
+  );
+
+  // IndirectY は読み出したアドレスに Y を足す
+  assert_eq!(
+    run(&[0xb1, 0x10], 0, 0x10, &[(0x10, 0xf8), (0x11, 0x03), (0x0408, 0x07)]).a,
+    0x07
+  );
+  assert_eq!(
+    run(&[0xb1, 0xff], 0, 0x02, &[(0xff, 0xff), (0x00, 0xff), (0x0001, 0x08)]).a,
+    0x08
+  );
+
+  // Relative は符号付きで PC (次の命令) から数える
+  assert_eq!(run(&[0xd0, 0xfe], 0, 0, &[]).pc, 0x0400);
+  assert_eq!(run(&[0xd0, 0x7f], 0, 0, &[]).pc, 0x0481);
+
+  // Indirect は $xxff を跨がない (上位バイトは $xx00 から読む)
+  let memory = [(0x02ff, 0x34), (0x0200, 0x12), (0x0300, 0x56)];
+  assert_eq!(run(&[0x6c, 0xff, 0x02], 0, 0, &memory).pc, 0x1234);
+  assert_eq!(run(&[0x6c, 0xfe, 0x02], 0, 0, &[(0x02fe, 0x78), (0x02ff, 0x56)]).pc, 0x5678);
+}
+
+#[test]
+fn memory_map() {
+  let path = "./roms/sample1.nes".to_string();
+  let mut machine = machine::Machine::new();
+  let cartridge = system::load_cassette(path, false).unwrap();
+  machine.set_cartridge(cartridge).unwrap();
+
+  // WRAM は $0800 ごとにミラーされる
+  machine.write(0x0012, 0x34);
+  assert_eq!(machine.read(0x0812), 0x34);
+  assert_eq!(machine.read(0x1812), 0x34);
+  machine.write(0x1fff, 0x56);
+  assert_eq!(machine.read(0x07ff), 0x56);
+
+  // PRG-RAM
+  machine.write(0x6000, 0x78);
+  assert_eq!(machine.read(0x6000), 0x78);
+
+  // PRG-ROM は $8000 から始まり書き換えられない
+  assert_eq!(machine.read(0x8000), 0x78);
+  machine.write(0x8000, 0x00);
+  assert_eq!(machine.read(0x8000), 0x78);
+}
+
+#[test]
+fn nrom_mirroring() {
+  let path = "./roms/nestest.nes".to_string();
+  let mut machine = machine::Machine::new();
+  let cartridge = system::load_cassette(path, false).unwrap();
+  assert_eq!(cartridge.header.mapper, 0);
+  machine.set_cartridge(cartridge).unwrap();
+
+  // 16KiB の PRG-ROM は $c000 にミラーされる
+  for addr in 0x8000..0xc000 {
+    assert_eq!(machine.read(addr), machine.read(addr + 0x4000));
+  }
+}
+
+// テスト用の iNES ファイルを作る
+#[cfg(test)]
+fn ines(mapper: u8, flags_6: u8, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Vec<u8> {
+  let mut buffer = vec![
+    0x4e,
+    0x45,
+    0x53,
+    0x1a,
+    (prg_rom.len() / 0x4000) as u8,
+    (chr_rom.len() / 0x2000) as u8,
+    (mapper << 4) | flags_6,
+    mapper & 0xf0,
+  ];
+  buffer.resize(16, 0);
+  buffer.extend(prg_rom);
+  buffer.extend(chr_rom);
+  buffer
+}
+
+// PPUADDR に書き込んで PPUDATA に書き込む
+#[cfg(test)]
+fn write_vram(machine: &mut machine::Machine, addr: u16, val: u8) {
+  machine.write(0x2006, (addr >> 8) as u8);
+  machine.write(0x2006, addr as u8);
+  machine.write(0x2007, val);
+}
+
+// 描画したフレームの (x, y) の色
+#[cfg(test)]
+fn pixel(ppu: &ppu::Ppu, x: usize, y: usize) -> Vec<u8> {
+  let offset = (y * ppu::WIDTH + x) * 3;
+  ppu.frame[offset..offset + 3].to_vec()
+}
+
+#[test]
+fn mmc1_bank_switch() {
+  // 16KiB x 8 の PRG-ROM, 4KiB x 4 の CHR-ROM の中身をバンク番号で埋める
+  let prg_rom = (0..8 * 0x4000).map(|addr| (addr / 0x4000) as u8).collect();
+  let chr_rom = (0..4 * 0x1000).map(|addr| (addr / 0x1000) as u8).collect();
+  let cartridge = cartridge::Cartridge::from_bytes(&ines(1, 0x00, prg_rom, chr_rom)).unwrap();
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge).unwrap();
+
+  // シフトレジスタに下位ビットから5回書き込む
+  fn write_serial(machine: &mut machine::Machine, addr: usize, val: u8) {
+    for i in 0..5 {
+      machine.write(addr, (val >> i) & 1);
+    }
+  }
+
+  // 電源投入時は最後のバンクが $c000 に固定される
+  assert_eq!(machine.read(0x8000), 0);
+  assert_eq!(machine.read(0xc000), 7);
+
+  write_serial(&mut machine, 0xe000, 3);
+  assert_eq!(machine.read(0x8000), 3);
+  assert_eq!(machine.read(0xc000), 7);
+
+  // $8000 固定, $c000 切り替えのモード
+  write_serial(&mut machine, 0x8000, 0x08);
+  assert_eq!(machine.read(0x8000), 0);
+  assert_eq!(machine.read(0xc000), 3);
+
+  // 32KiB 単位の切り替え
+  write_serial(&mut machine, 0x8000, 0x00);
+  write_serial(&mut machine, 0xe000, 5);
+  assert_eq!(machine.read(0x8000), 4);
+  assert_eq!(machine.read(0xc000), 5);
+
+  // 4KiB 単位の CHR 切り替え
+  write_serial(&mut machine, 0x8000, 0x10);
+  write_serial(&mut machine, 0xa000, 2);
+  write_serial(&mut machine, 0xc000, 1);
+  assert_eq!(machine.read_chr(0x0000), 2);
+  assert_eq!(machine.read_chr(0x1000), 1);
+
+  // bit 7 を書き込むとシフトレジスタがリセットされる
+  machine.write(0xe000, 1);
+  machine.write(0xe000, 0x80);
+  write_serial(&mut machine, 0xe000, 6);
+  assert_eq!(machine.read(0x8000), 6);
+}
+
+#[test]
+fn mmc1_rmw() {
+  // INC $e000 ($e000 の値は 7), LDA #$01, STA $e000, LSR A, STA $e000 x 3
+  let mut prg_rom: Vec<u8> = (0..8 * 0x4000).map(|addr| (addr / 0x4000) as u8).collect();
+  let code = [
+    0xee, 0x00, 0xe0, 0xa9, 0x01, 0x8d, 0x00, 0xe0, 0x4a, 0x8d, 0x00, 0xe0, 0x8d, 0x00, 0xe0, 0x8d,
+    0x00, 0xe0,
+  ];
+  prg_rom[0x1c000..0x1c000 + code.len()].copy_from_slice(&code);
+  let buffer = ines(1, 0x00, prg_rom, vec![0; 0x2000]);
+
+  // RMW 命令の2回の書き込みは最初の空書き (7 の bit 0) だけがシフトレジスタに入り, PRG bank = 0b00011
+  for cycle_step in [false, true] {
+    let mut machine = machine::Machine::new();
+    machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+    let mut cpu = cpu::Cpu::new();
+    cpu.pc = 0xc000;
+    cpu.cycle_step = cycle_step;
+
+    for _ in 0..7 {
+      cpu.exec(&mut machine);
+    }
+    assert_eq!(machine.read(0x8000), 3, "cycle_step = {}", cycle_step);
+  }
+}
+
+#[test]
+fn mmc3_bank_switch_and_irq() {
+  // 8KiB x 8 の PRG-ROM, 1KiB x 16 の CHR-ROM の中身をバンク番号で埋める
+  let prg_rom = (0..8 * 0x2000).map(|addr| (addr / 0x2000) as u8).collect();
+  let chr_rom = (0..16 * 0x400).map(|addr| (addr / 0x400) as u8).collect();
+  let cartridge = cartridge::Cartridge::from_bytes(&ines(4, 0x01, prg_rom, chr_rom)).unwrap();
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge).unwrap();
+
+  // R6 = 2, R7 = 3
+  machine.write(0x8000, 6);
+  machine.write(0x8001, 2);
+  machine.write(0x8000, 7);
+  machine.write(0x8001, 3);
+  assert_eq!(machine.read(0x8000), 2);
+  assert_eq!(machine.read(0xa000), 3);
+  assert_eq!(machine.read(0xc000), 6);
+  assert_eq!(machine.read(0xe000), 7);
+
+  // PRG の反転モード
+  machine.write(0x8000, 0x46);
+  assert_eq!(machine.read(0x8000), 6);
+  assert_eq!(machine.read(0xc000), 2);
+
+  // R0 = 4 (2KiB), R2 = 9 (1KiB), CHR A12 の反転
+  machine.write(0x8000, 0);
+  machine.write(0x8001, 4);
+  machine.write(0x8000, 2);
+  machine.write(0x8001, 9);
+  assert_eq!(machine.read_chr(0x0000), 4);
+  assert_eq!(machine.read_chr(0x0400), 5);
+  assert_eq!(machine.read_chr(0x1000), 9);
+  machine.write(0x8000, 0x80);
+  assert_eq!(machine.read_chr(0x1000), 4);
+  assert_eq!(machine.read_chr(0x0000), 9);
+  machine.write(0x8000, 0);
+
+  // 2スキャンラインごとに IRQ
+  machine.write(0xc000, 2);
+  machine.write(0xc001, 0);
+  machine.write(0xe001, 0);
+
+  fn scanline(machine: &mut machine::Machine) {
+    // 背景は $0000, スプライトは $1000 から読むので A12 が1回立ち上がる
+    for addr in 0..16 {
+      machine.read_chr(addr);
+    }
+    machine.read_chr(0x1000);
+  }
+
+  scanline(&mut machine); // reload -> 2
+  assert!(!machine.irq());
+  scanline(&mut machine); // 1
+  assert!(!machine.irq());
+  scanline(&mut machine); // 0
+  assert!(machine.irq());
+
+  machine.write(0xe000, 0);
+  assert!(!machine.irq());
+}
+
+#[test]
+fn peek() {
+  let path = "./roms/sample1.nes".to_string();
+  let mut machine = machine::Machine::new();
+  let cartridge = system::load_cassette(path, false).unwrap();
+  machine.set_cartridge(cartridge).unwrap();
+
+  machine.write(0x0012, 0x34);
+  for addr in [0x0012, 0x0812, 0x6000, 0x8000, 0xfffc, 0xfffd] {
+    assert_eq!(machine.peek(addr), machine.read(addr));
+  }
+
+  // peek_chr は MMC3 の IRQ カウンタを進めない
+  let prg_rom = vec![0; 8 * 0x2000];
+  let chr_rom = (0..16 * 0x400).map(|addr| (addr / 0x400) as u8).collect();
+  let cartridge = cartridge::Cartridge::from_bytes(&ines(4, 0x01, prg_rom, chr_rom)).unwrap();
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge).unwrap();
+  machine.write(0xc000, 0);
+  machine.write(0xc001, 0);
+  machine.write(0xe001, 0);
+
+  for _ in 0..3 {
+    for addr in 0..16 {
+      machine.peek_chr(addr);
+    }
+    machine.peek_chr(0x1000);
+  }
+  assert!(!machine.irq());
+
+  for addr in 0..16 {
+    machine.read_chr(addr);
+  }
+  machine.read_chr(0x1000);
+  assert!(machine.irq());
+}
+
+#[test]
+fn discrete_mappers() {
+  fn insert(number: u8, prg_banks: usize, prg_size: usize, chr_banks: usize) -> machine::Machine {
+    // 中身をバンク番号で埋める
+    let prg_rom = (0..prg_banks * prg_size).map(|addr| 0xf0 | (addr / prg_size) as u8).collect();
+    let chr_rom = (0..chr_banks * 0x2000).map(|addr| (addr / 0x2000) as u8).collect();
+    let cartridge = cartridge::Cartridge::from_bytes(&ines(number, 0x01, prg_rom, chr_rom)).unwrap();
+    let mut machine = machine::Machine::new();
+    machine.set_cartridge(cartridge).unwrap();
+    machine
+  }
+
+  // UxROM: $c000 は最後のバンクに固定, ROM の値 (0xf0 | bank) と AND される
+  let mut machine = insert(2, 4, 0x4000, 1);
+  machine.write(0xc000, 0x02);
+  assert_eq!(machine.read(0x8000), 0xf2);
+  assert_eq!(machine.read(0xc000), 0xf3);
+  machine.write(0x8000, 0x0d); // 0x0d & 0xf2
+  assert_eq!(machine.read(0x8000), 0xf0);
+
+  // CNROM
+  let mut machine = insert(3, 4, 0x2000, 4);
+  machine.write(0xe000, 0x03); // 0x03 & 0xf3
+  assert_eq!(machine.read_chr(0x0000), 3);
+
+  // AxROM: 1画面ミラーリングの切り替え
+  let mut machine = insert(7, 4, 0x8000, 1);
+  assert_eq!(machine.mirroring(), Some(mapper::Mirroring::SingleScreenLower));
+  machine.write(0x8000, 0x12);
+  assert_eq!(machine.read(0x8000), 0xf2);
+  assert_eq!(machine.mirroring(), Some(mapper::Mirroring::SingleScreenUpper));
+
+  // GxROM
+  let mut machine = insert(66, 4, 0x8000, 4);
+  machine.write(0x8000, 0x31); // 0x31 & 0xf0
+  assert_eq!(machine.read(0xffff), 0xf3);
+  assert_eq!(machine.read_chr(0x1fff), 0);
+  machine.write(0x8000, 0x31); // 0x31 & 0xf3
+  assert_eq!(machine.read_chr(0x1fff), 1);
+}
+
+#[test]
+fn ines_header() {
+  // iNES 1.0: MMC1, バッテリー, trainer あり
+  let mut buffer = ines(1, 0x06, vec![0; 0x4000], vec![0; 0x2000]);
+  buffer.splice(16..16, vec![0xff; 512]);
+  buffer[16 + 512] = 0x12;
+  let cartridge = cartridge::Cartridge::from_bytes(&buffer).unwrap();
+  assert_eq!(cartridge.header.format, cartridge::Format::Ines);
+  assert_eq!(cartridge.header.mapper, 1);
+  assert!(cartridge.header.battery);
+  assert_eq!(cartridge.header.prg_nvram_bytes, 0x2000);
+  assert_eq!(cartridge.prg_rom[0], 0x12); // trainer は読み飛ばす
+
+  // NES 2.0: マッパー 0x104, サブマッパー 2, PAL, CHR-RAM 8KiB
+  let mut buffer = ines(4, 0x01, vec![0; 0x4000], vec![]);
+  buffer[7] |= 0x08;
+  buffer[8] = 0x21;
+  buffer[10] = 0x70; // PRG-NVRAM 8KiB
+  buffer[11] = 0x07; // CHR-RAM 8KiB
+  buffer[12] = 0x01;
+  let header = cartridge::Header::parse(&buffer).unwrap();
+  assert_eq!(header.format, cartridge::Format::Nes2);
+  assert_eq!(header.mapper, 0x104);
+  assert_eq!(header.submapper, 2);
+  assert_eq!(header.mirroring, mapper::Mirroring::Vertical);
+  assert_eq!(header.timing, cartridge::Timing::Pal);
+  assert_eq!(header.prg_ram_bytes, 0);
+  assert_eq!(header.prg_nvram_bytes, 0x2000);
+  assert_eq!(header.chr_ram_bytes, 0x2000);
+
+  // NES 2.0 の指数表記: 2^14 * 3
+  buffer[4] = (14 << 2) | 1;
+  buffer[9] = 0x0f;
+  assert_eq!(cartridge::Header::parse(&buffer).unwrap().prg_rom_bytes, 0xc000);
+
+  // 壊れたファイル
+  assert!(matches!(
+    cartridge::Header::parse(b"NEZ\x1a"),
+    Err(cartridge::LoadError::BadMagic)
+  ));
+  assert!(matches!(
+    cartridge::Header::parse(b"NES\x1a\x01"),
+    Err(cartridge::LoadError::TruncatedHeader(5))
+  ));
+  let buffer = ines(0, 0x00, vec![0; 0x4000], vec![0; 0x2000]);
+  assert!(matches!(
+    cartridge::Cartridge::from_bytes(&buffer[..0x100]),
+    Err(cartridge::LoadError::TruncatedPrg { expected: 0x4000, actual: 0xf0 })
+  ));
+  assert!(matches!(
+    cartridge::Cartridge::from_bytes(&buffer[..0x4010 + 0x10]),
+    Err(cartridge::LoadError::TruncatedChr { expected: 0x2000, actual: 0x10 })
+  ));
+}
+
+#[test]
+fn load_error() {
+  let mut machine = machine::Machine::new();
+
+  let result = system::load_cassette("./roms/missing.nes".to_string(), false);
+  assert!(matches!(result, Err(cartridge::LoadError::Io(_))));
+
+  let buffer = ines(5, 0x00, vec![0; 0x4000], vec![0; 0x2000]);
+  let cartridge = cartridge::Cartridge::from_bytes(&buffer).unwrap();
+  assert!(matches!(
+    machine.set_cartridge(cartridge),
+    Err(cartridge::LoadError::UnsupportedMapper(5))
+  ));
+
+  // NROM は 32KiB までしか載らない
+  let buffer = ines(0, 0x00, vec![0; 0xc000], vec![0; 0x2000]);
+  let cartridge = cartridge::Cartridge::from_bytes(&buffer).unwrap();
+  assert!(matches!(
+    machine.set_cartridge(cartridge),
+    Err(cartridge::LoadError::OversizedBanks { rom: "PRG-ROM", bytes: 0xc000, max: 0x8000 })
+  ));
+}
+
+#[test]
+fn chr_ram() {
+  let buffer = ines(2, 0x00, vec![0; 0x8000], vec![]);
+  let cartridge = cartridge::Cartridge::from_bytes(&buffer).unwrap();
+  assert_eq!(cartridge.chr_ram.len(), 0x2000);
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge).unwrap();
+
+  // $2006 で $0010 を指定して $2007 でタイルを書き込む
+  machine.write(0x2006, 0x00);
+  machine.write(0x2006, 0x10);
+  for val in 1..=16 {
+    machine.write(0x2007, val);
+  }
+  for addr in 0..16 {
+    assert_eq!(machine.read_chr(0x10 + addr), addr as u8 + 1);
+  }
+
+  // 32 ずつ進める
+  machine.write(0x2000, 0x04);
+  machine.write(0x2006, 0x1f);
+  machine.write(0x2006, 0x00);
+  machine.write(0x2007, 0xaa);
+  machine.write(0x2007, 0xbb);
+  assert_eq!(machine.read_chr(0x1f00), 0xaa);
+  assert_eq!(machine.read_chr(0x1f20), 0xbb);
+}
+
+#[test]
+fn battery_save() {
+  let path = std::env::temp_dir().join("nes-battery-save.sav");
+  let _ = std::fs::remove_file(&path);
+
+  // MMC1, バッテリーあり
+  let buffer = ines(1, 0x02, vec![0; 0x8000], vec![0; 0x2000]);
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+  assert!(machine.battery());
+  system::load_save(&mut machine, &path).unwrap(); // 無くても良い
+
+  machine.write(0x6000, 0x12);
+  machine.write(0x7fff, 0x34);
+
+  // 書き出せなかったら書き換えの印は残る
+  let missing = std::env::temp_dir().join("nes-battery-save").join("missing.sav");
+  assert!(system::write_save(&mut machine, &missing).is_err());
+  assert!(machine.prg_ram_dirty());
+
+  system::write_save(&mut machine, &path).unwrap();
+  assert!(!machine.prg_ram_dirty());
+  assert_eq!(std::fs::read(&path).unwrap().len(), 0x2000);
+
+  // 別のカセットで読み込む
+  let mut machine = machine::Machine::new();
+  machine.set_cartridge(cartridge::Cartridge::from_bytes(&buffer).unwrap()).unwrap();
+  system::load_save(&mut machine, &path).unwrap();
+  assert_eq!(machine.read(0x6000), 0x12);
+  assert_eq!(machine.read(0x7fff), 0x34);
+
+  std::fs::remove_file(&path).unwrap();
 }
-*/
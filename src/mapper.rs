@@ -0,0 +1,144 @@
+use super::cartridge::{Cartridge, LoadError};
+
+mod axrom;
+mod cnrom;
+mod gxrom;
+mod mmc1;
+mod mmc3;
+mod nrom;
+mod uxrom;
+
+/// ネームテーブルのミラーリング
+///
+/// https://wiki.nesdev.com/w/index.php/Mirroring
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mirroring {
+  /// $2000 = $2400, $2800 = $2c00 (縦スクロール向け)
+  Horizontal,
+  /// $2000 = $2800, $2400 = $2c00 (横スクロール向け)
+  Vertical,
+  /// すべて $2000 の1画面を参照する
+  SingleScreenLower,
+  /// すべて $2400 の1画面を参照する
+  SingleScreenUpper,
+  /// カセット側に VRAM があり4画面を別々に持つ
+  FourScreen,
+}
+
+impl Mirroring {
+  /// 論理ネームテーブル (0 = $2000, 1 = $2400, 2 = $2800, 3 = $2c00) が参照する物理ネームテーブル
+  ///
+  /// 本体の VRAM は 0, 1 の2画面分で, 2, 3 は4画面のときだけカセット側の VRAM を使います
+  pub fn nametable(&self, index: usize) -> usize {
+    match self {
+      Mirroring::Horizontal => index / 2,
+      Mirroring::Vertical => index % 2,
+      Mirroring::SingleScreenLower => 0,
+      Mirroring::SingleScreenUpper => 1,
+      Mirroring::FourScreen => index,
+    }
+  }
+}
+
+/// # マッパー
+///
+/// カセットの基板ごとに異なるバンク切り替えの仕組みを抽象化します
+///
+/// | Method | Description |
+/// | - | - |
+/// | `cartridge`, `cartridge_mut` | ROM, RAM とヘッダ |
+/// | `read`, `write` | CPU $4020 ~ $ffff (拡張 ROM, PRG-RAM, PRG-ROM) へのアクセス |
+/// | `read_chr`, `write_chr` | PPU $0000 ~ $1fff (パターンテーブル) へのアクセス |
+/// | `peek`, `peek_chr` | 状態を変えずに `read`, `read_chr` と同じ値を返す (デバッグ用) |
+/// | `mirroring` | 現在のネームテーブルのミラーリング |
+/// | `irq` | カセットが IRQ を要求しているか |
+/// | `ppu_address` | PPU がアドレスバスに出したアドレスの通知 (A12 の監視用) |
+/// | `cpu_cycle` | CPU のバスアクセスの通知 (連続した書き込みの検出用) |
+pub trait Mapper {
+  fn cartridge(&self) -> &Cartridge;
+  fn cartridge_mut(&mut self) -> &mut Cartridge;
+
+  fn peek(&self, addr: usize) -> u8;
+  fn peek_chr(&self, addr: usize) -> u8;
+
+  // 読み出しで状態が変わるマッパーだけ上書きする
+  fn read(&mut self, addr: usize) -> u8 {
+    self.peek(addr)
+  }
+  fn write(&mut self, addr: usize, val: u8);
+
+  fn read_chr(&mut self, addr: usize) -> u8 {
+    self.peek_chr(addr)
+  }
+  fn write_chr(&mut self, addr: usize, val: u8);
+
+  fn mirroring(&self) -> Mirroring;
+
+  fn irq(&self) -> bool {
+    false
+  }
+
+  fn ppu_address(&mut self, _addr: usize) {}
+  fn cpu_cycle(&mut self) {}
+}
+
+// ROM のサイズがマッパーで切り替えられる範囲に収まっているか
+fn check_size(cartridge: &Cartridge, max_prg: usize, max_chr: usize) -> Result<(), LoadError> {
+  if cartridge.prg_rom.len() > max_prg {
+    return Err(LoadError::OversizedBanks {
+      rom: "PRG-ROM",
+      bytes: cartridge.prg_rom.len(),
+      max: max_prg,
+    });
+  }
+  if cartridge.chr_rom.len() > max_chr {
+    return Err(LoadError::OversizedBanks {
+      rom: "CHR-ROM",
+      bytes: cartridge.chr_rom.len(),
+      max: max_chr,
+    });
+  }
+  Ok(())
+}
+
+// バスの競合 (ROM も同時に値を出すため, 書き込んだ値は同じアドレスの ROM の値との AND になる)
+// https://wiki.nesdev.com/w/index.php/Bus_conflict
+fn bus_conflict(mapper: &dyn Mapper, addr: usize, val: u8) -> u8 {
+  val & mapper.peek(addr)
+}
+
+// ヘッダのマッパー番号から対応するマッパーを作る
+// https://wiki.nesdev.com/w/index.php/Mapper
+pub fn new(cartridge: Cartridge) -> Result<Box<dyn Mapper>, LoadError> {
+  match cartridge.header.mapper {
+    0 => {
+      check_size(&cartridge, 0x8000, 0x2000)?;
+      Ok(Box::new(nrom::Nrom::new(cartridge)))
+    }
+    1 => {
+      check_size(&cartridge, 0x40000, 0x20000)?;
+      Ok(Box::new(mmc1::Mmc1::new(cartridge)))
+    }
+    2 => {
+      check_size(&cartridge, 0x400000, 0x2000)?;
+      Ok(Box::new(uxrom::Uxrom::new(cartridge)))
+    }
+    3 => {
+      check_size(&cartridge, 0x8000, 0x200000)?;
+      Ok(Box::new(cnrom::Cnrom::new(cartridge)))
+    }
+    4 => {
+      check_size(&cartridge, 0x80000, 0x40000)?;
+      Ok(Box::new(mmc3::Mmc3::new(cartridge)))
+    }
+    7 => {
+      check_size(&cartridge, 0x40000, 0x2000)?;
+      Ok(Box::new(axrom::Axrom::new(cartridge)))
+    }
+    66 => {
+      check_size(&cartridge, 0x20000, 0x8000)?;
+      Ok(Box::new(gxrom::Gxrom::new(cartridge)))
+    }
+    n => Err(LoadError::UnsupportedMapper(n)),
+  }
+}
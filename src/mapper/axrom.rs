@@ -0,0 +1,68 @@
+use super::super::cartridge::Cartridge;
+use super::{Mapper, Mirroring};
+
+/// # AxROM (Mapper 7)
+///
+/// PRG-ROM を 32KiB 単位で切り替え、bit 4 で1画面ミラーリングのページを選びます
+///
+/// | Bit | Description |
+/// | - | - |
+/// | 0 ~ 2 | PRG bank (32KiB) |
+/// | 4 | 0 = $2000, 1 = $2400 の1画面 |
+///
+/// https://wiki.nesdev.com/w/index.php/AxROM
+pub struct Axrom {
+  cartridge: Cartridge,
+  prg_bank: u8,
+  mirroring: Mirroring,
+}
+
+impl Axrom {
+  pub fn new(cartridge: Cartridge) -> Self {
+    Self {
+      cartridge,
+      prg_bank: 0,
+      mirroring: Mirroring::SingleScreenLower,
+    }
+  }
+}
+
+impl Mapper for Axrom {
+  fn cartridge(&self) -> &Cartridge {
+    &self.cartridge
+  }
+
+  fn cartridge_mut(&mut self) -> &mut Cartridge {
+    &mut self.cartridge
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    match addr {
+      0x8000..=0xffff => self.cartridge.read_prg(self.prg_bank as usize, 0x8000, addr - 0x8000),
+      _ => 0,
+    }
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    if let 0x8000..=0xffff = addr {
+      self.prg_bank = val & 0x07;
+      self.mirroring = if val & 0x10 == 0 {
+        Mirroring::SingleScreenLower
+      } else {
+        Mirroring::SingleScreenUpper
+      };
+    }
+  }
+
+  fn peek_chr(&self, addr: usize) -> u8 {
+    self.cartridge.read_chr(0, 0, addr)
+  }
+
+  fn write_chr(&mut self, addr: usize, val: u8) {
+    self.cartridge.write_chr(0, 0, addr, val);
+  }
+
+  fn mirroring(&self) -> Mirroring {
+    self.mirroring
+  }
+}
@@ -0,0 +1,56 @@
+use super::super::cartridge::Cartridge;
+use super::{bus_conflict, Mapper, Mirroring};
+
+/// # CNROM (Mapper 3)
+///
+/// PRG-ROM は固定で、CHR-ROM を 8KiB 単位で切り替えます
+///
+/// https://wiki.nesdev.com/w/index.php/CNROM
+pub struct Cnrom {
+  cartridge: Cartridge,
+  chr_bank: u8,
+}
+
+impl Cnrom {
+  pub fn new(cartridge: Cartridge) -> Self {
+    Self {
+      cartridge,
+      chr_bank: 0,
+    }
+  }
+}
+
+impl Mapper for Cnrom {
+  fn cartridge(&self) -> &Cartridge {
+    &self.cartridge
+  }
+
+  fn cartridge_mut(&mut self) -> &mut Cartridge {
+    &mut self.cartridge
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    match addr {
+      0x8000..=0xffff => self.cartridge.read_prg(0, 0, addr - 0x8000),
+      _ => 0,
+    }
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    if let 0x8000..=0xffff = addr {
+      self.chr_bank = bus_conflict(self, addr, val);
+    }
+  }
+
+  fn peek_chr(&self, addr: usize) -> u8 {
+    self.cartridge.read_chr(self.chr_bank as usize, 0x2000, addr)
+  }
+
+  fn write_chr(&mut self, addr: usize, val: u8) {
+    self.cartridge.write_chr(self.chr_bank as usize, 0x2000, addr, val);
+  }
+
+  fn mirroring(&self) -> Mirroring {
+    self.cartridge.header.mirroring
+  }
+}
@@ -0,0 +1,65 @@
+use super::super::cartridge::Cartridge;
+use super::{bus_conflict, Mapper, Mirroring};
+
+/// # GxROM (Mapper 66)
+///
+/// PRG-ROM を 32KiB 単位、CHR-ROM を 8KiB 単位で切り替えます
+///
+/// | Bit | Description |
+/// | - | - |
+/// | 0 ~ 1 | CHR bank (8KiB) |
+/// | 4 ~ 5 | PRG bank (32KiB) |
+///
+/// https://wiki.nesdev.com/w/index.php/GxROM
+pub struct Gxrom {
+  cartridge: Cartridge,
+  prg_bank: u8,
+  chr_bank: u8,
+}
+
+impl Gxrom {
+  pub fn new(cartridge: Cartridge) -> Self {
+    Self {
+      cartridge,
+      prg_bank: 0,
+      chr_bank: 0,
+    }
+  }
+}
+
+impl Mapper for Gxrom {
+  fn cartridge(&self) -> &Cartridge {
+    &self.cartridge
+  }
+
+  fn cartridge_mut(&mut self) -> &mut Cartridge {
+    &mut self.cartridge
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    match addr {
+      0x8000..=0xffff => self.cartridge.read_prg(self.prg_bank as usize, 0x8000, addr - 0x8000),
+      _ => 0,
+    }
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    if let 0x8000..=0xffff = addr {
+      let val = bus_conflict(self, addr, val);
+      self.prg_bank = (val >> 4) & 0x03;
+      self.chr_bank = val & 0x03;
+    }
+  }
+
+  fn peek_chr(&self, addr: usize) -> u8 {
+    self.cartridge.read_chr(self.chr_bank as usize, 0x2000, addr)
+  }
+
+  fn write_chr(&mut self, addr: usize, val: u8) {
+    self.cartridge.write_chr(self.chr_bank as usize, 0x2000, addr, val);
+  }
+
+  fn mirroring(&self) -> Mirroring {
+    self.cartridge.header.mirroring
+  }
+}
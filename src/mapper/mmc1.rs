@@ -0,0 +1,173 @@
+use super::super::cartridge::Cartridge;
+use super::{Mapper, Mirroring};
+
+/// # MMC1 (Mapper 1)
+///
+/// $8000 ~ $ffff への5回の書き込みでシフトレジスタに値を溜め、
+/// 5回目の書き込みアドレスによって転送先のレジスタが決まります。
+/// 連続したサイクルの書き込みは最初の1回だけが有効で, RMW 命令は読んだ値の空書きが使われます
+///
+/// | Address | Register |
+/// | - | - |
+/// | $8000 ~ $9fff | Control (ミラーリング, PRG/CHR のバンクモード) |
+/// | $a000 ~ $bfff | CHR bank 0 |
+/// | $c000 ~ $dfff | CHR bank 1 |
+/// | $e000 ~ $ffff | PRG bank (bit 4 で PRG-RAM を無効化) |
+///
+/// https://wiki.nesdev.com/w/index.php/MMC1
+pub struct Mmc1 {
+  cartridge: Cartridge,
+
+  shift_register: u8,
+  shift_count: u8,
+
+  // 連続した書き込みを無視するための CPU のバスアクセス数
+  cycles: u64,
+  last_write: Option<u64>,
+
+  control: u8,
+  chr_bank_0: u8,
+  chr_bank_1: u8,
+  prg_bank: u8,
+}
+
+impl Mmc1 {
+  pub fn new(cartridge: Cartridge) -> Self {
+    Self {
+      cartridge,
+
+      shift_register: 0,
+      shift_count: 0,
+
+      cycles: 0,
+      last_write: None,
+
+      // 電源投入時は最後のバンクが $c000 に固定されている
+      control: 0x0c,
+      chr_bank_0: 0,
+      chr_bank_1: 0,
+      prg_bank: 0,
+    }
+  }
+
+  fn prg_ram_enabled(&self) -> bool {
+    self.prg_bank & 0x10 == 0
+  }
+
+  fn write_register(&mut self, addr: usize, val: u8) {
+    match addr {
+      0x8000..=0x9fff => self.control = val,
+      0xa000..=0xbfff => self.chr_bank_0 = val,
+      0xc000..=0xdfff => self.chr_bank_1 = val,
+      _ => self.prg_bank = val,
+    }
+  }
+
+  // CPU $8000 ~ $ffff を 16KiB 単位のバンク番号に変換する
+  fn prg_bank_of(&self, addr: usize) -> usize {
+    let bank = (self.prg_bank & 0x0f) as usize;
+    let last = (self.cartridge.prg_rom.len() / 0x4000).saturating_sub(1);
+
+    match ((self.control >> 2) & 0x03, addr) {
+      // 32KiB 単位で切り替え (最下位ビットは無視)
+      (0, _) | (1, _) => (bank & !1) | ((addr - 0x8000) / 0x4000),
+      // $8000 を最初のバンクに固定, $c000 を切り替え
+      (2, 0x8000..=0xbfff) => 0,
+      (2, _) => bank,
+      // $8000 を切り替え, $c000 を最後のバンクに固定
+      (_, 0x8000..=0xbfff) => bank,
+      (_, _) => last,
+    }
+  }
+
+  // PPU $0000 ~ $1fff を 4KiB 単位のバンク番号に変換する
+  fn chr_bank_of(&self, addr: usize) -> usize {
+    if self.control & 0x10 == 0 {
+      // 8KiB 単位で切り替え (最下位ビットは無視)
+      (self.chr_bank_0 & !1) as usize | (addr / 0x1000)
+    } else if addr < 0x1000 {
+      self.chr_bank_0 as usize
+    } else {
+      self.chr_bank_1 as usize
+    }
+  }
+}
+
+impl Mapper for Mmc1 {
+  fn cartridge(&self) -> &Cartridge {
+    &self.cartridge
+  }
+
+  fn cartridge_mut(&mut self) -> &mut Cartridge {
+    &mut self.cartridge
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    match addr {
+      0x6000..=0x7fff if self.prg_ram_enabled() => self.cartridge.read_prg_ram(addr),
+      0x8000..=0xffff => {
+        let bank = self.prg_bank_of(addr);
+        self.cartridge.read_prg(bank, 0x4000, addr & 0x3fff)
+      }
+      _ => 0,
+    }
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    match addr {
+      0x6000..=0x7fff if self.prg_ram_enabled() => self.cartridge.write_prg_ram(addr, val),
+
+      0x8000..=0xffff => {
+        // 直前のサイクルにも書き込んでいたら無視する
+        let consecutive = self.last_write.map(|cycle| cycle + 1) == Some(self.cycles);
+        self.last_write = Some(self.cycles);
+        if consecutive {
+          return;
+        }
+
+        // bit 7 が立っていたらシフトレジスタをリセット
+        if val & 0x80 != 0 {
+          self.shift_register = 0;
+          self.shift_count = 0;
+          self.control |= 0x0c;
+          return;
+        }
+
+        // 下位ビットから順に5bit溜める
+        self.shift_register |= (val & 0x01) << self.shift_count;
+        self.shift_count += 1;
+
+        if self.shift_count == 5 {
+          self.write_register(addr, self.shift_register);
+          self.shift_register = 0;
+          self.shift_count = 0;
+        }
+      }
+
+      _ => {}
+    }
+  }
+
+  fn peek_chr(&self, addr: usize) -> u8 {
+    let bank = self.chr_bank_of(addr);
+    self.cartridge.read_chr(bank, 0x1000, addr & 0x0fff)
+  }
+
+  fn write_chr(&mut self, addr: usize, val: u8) {
+    let bank = self.chr_bank_of(addr);
+    self.cartridge.write_chr(bank, 0x1000, addr & 0x0fff, val);
+  }
+
+  fn cpu_cycle(&mut self) {
+    self.cycles += 1;
+  }
+
+  fn mirroring(&self) -> Mirroring {
+    match self.control & 0x03 {
+      0 => Mirroring::SingleScreenLower,
+      1 => Mirroring::SingleScreenUpper,
+      2 => Mirroring::Vertical,
+      _ => Mirroring::Horizontal,
+    }
+  }
+}
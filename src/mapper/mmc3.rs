@@ -0,0 +1,202 @@
+use super::super::cartridge::Cartridge;
+use super::{Mapper, Mirroring};
+
+// A12 が low のまま続いたアクセス回数がこれ未満の立ち上がりは無視する
+// (スプライトのフェッチ中に挟まるネームテーブルのダミーアクセスを除くため)
+const A12_FILTER: u8 = 3;
+
+/// # MMC3 (Mapper 4)
+///
+/// 偶数/奇数アドレスの組でレジスタを操作します
+///
+/// | Address | Even | Odd |
+/// | - | - | - |
+/// | $8000 ~ $9fff | Bank select | Bank data |
+/// | $a000 ~ $bfff | Mirroring | PRG-RAM protect |
+/// | $c000 ~ $dfff | IRQ latch | IRQ reload |
+/// | $e000 ~ $ffff | IRQ disable | IRQ enable |
+///
+/// IRQ カウンタは PPU のアドレス線 A12 の立ち上がりでデクリメントされ、
+/// 0 になったときに IRQ を要求します (通常はスキャンラインごとに1回)
+///
+/// https://wiki.nesdev.com/w/index.php/MMC3
+pub struct Mmc3 {
+  cartridge: Cartridge,
+
+  bank_select: u8,
+  registers: [u8; 8], // R0 ~ R7
+  mirroring: Mirroring,
+  prg_ram_enabled: bool,
+  prg_ram_protected: bool,
+
+  irq_latch: u8,
+  irq_counter: u8,
+  irq_reload: bool,
+  irq_enabled: bool,
+  irq_pending: bool,
+
+  a12: bool,
+  a12_low_count: u8,
+}
+
+impl Mmc3 {
+  pub fn new(cartridge: Cartridge) -> Self {
+    let mirroring = cartridge.header.mirroring;
+
+    Self {
+      cartridge,
+
+      bank_select: 0,
+      registers: [0; 8],
+      mirroring,
+      prg_ram_enabled: true,
+      prg_ram_protected: false,
+
+      irq_latch: 0,
+      irq_counter: 0,
+      irq_reload: false,
+      irq_enabled: false,
+      irq_pending: false,
+
+      a12: false,
+      a12_low_count: 0,
+    }
+  }
+
+  // CPU $8000 ~ $ffff を 8KiB 単位のバンク番号に変換する
+  fn prg_bank_of(&self, addr: usize) -> usize {
+    let banks = self.cartridge.prg_rom.len() / 0x2000;
+    let second_last = banks.saturating_sub(2);
+    let last = banks.saturating_sub(1);
+    let inverted = self.bank_select & 0x40 != 0;
+
+    match (addr, inverted) {
+      (0x8000..=0x9fff, false) | (0xc000..=0xdfff, true) => self.registers[6] as usize & 0x3f,
+      (0x8000..=0x9fff, true) | (0xc000..=0xdfff, false) => second_last,
+      (0xa000..=0xbfff, _) => self.registers[7] as usize & 0x3f,
+      _ => last,
+    }
+  }
+
+  // PPU $0000 ~ $1fff を 1KiB 単位のバンク番号に変換する
+  fn chr_bank_of(&self, addr: usize) -> usize {
+    // CHR A12 の反転
+    let addr = if self.bank_select & 0x80 != 0 { addr ^ 0x1000 } else { addr };
+
+    match addr {
+      // R0, R1 は 2KiB 単位 (最下位ビットは無視)
+      0x0000..=0x07ff => (self.registers[0] & 0xfe) as usize | (addr >> 10 & 1),
+      0x0800..=0x0fff => (self.registers[1] & 0xfe) as usize | (addr >> 10 & 1),
+      _ => self.registers[2 + ((addr - 0x1000) >> 10)] as usize,
+    }
+  }
+
+  // A12 の立ち上がりで IRQ カウンタを進める
+  fn clock_irq_counter(&mut self) {
+    if self.irq_counter == 0 || self.irq_reload {
+      self.irq_counter = self.irq_latch;
+      self.irq_reload = false;
+    } else {
+      self.irq_counter -= 1;
+    }
+
+    if self.irq_counter == 0 && self.irq_enabled {
+      self.irq_pending = true;
+    }
+  }
+}
+
+impl Mapper for Mmc3 {
+  fn cartridge(&self) -> &Cartridge {
+    &self.cartridge
+  }
+
+  fn cartridge_mut(&mut self) -> &mut Cartridge {
+    &mut self.cartridge
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    match addr {
+      0x6000..=0x7fff if self.prg_ram_enabled => self.cartridge.read_prg_ram(addr),
+      0x8000..=0xffff => {
+        let bank = self.prg_bank_of(addr);
+        self.cartridge.read_prg(bank, 0x2000, addr & 0x1fff)
+      }
+      _ => 0,
+    }
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    let even = addr & 1 == 0;
+
+    match addr {
+      0x6000..=0x7fff if self.prg_ram_enabled && !self.prg_ram_protected => {
+        self.cartridge.write_prg_ram(addr, val);
+      }
+
+      0x8000..=0x9fff if even => self.bank_select = val,
+      0x8000..=0x9fff => self.registers[(self.bank_select & 0x07) as usize] = val,
+
+      // 4画面のカセットはミラーリングを切り替えられない
+      0xa000..=0xbfff if even && self.mirroring == Mirroring::FourScreen => {}
+      0xa000..=0xbfff if even => {
+        self.mirroring = if val & 1 == 0 {
+          Mirroring::Vertical
+        } else {
+          Mirroring::Horizontal
+        };
+      }
+      0xa000..=0xbfff => {
+        self.prg_ram_enabled = val & 0x80 != 0;
+        self.prg_ram_protected = val & 0x40 != 0;
+      }
+
+      0xc000..=0xdfff if even => self.irq_latch = val,
+      0xc000..=0xdfff => {
+        self.irq_counter = 0;
+        self.irq_reload = true;
+      }
+
+      0xe000..=0xffff if even => {
+        self.irq_enabled = false;
+        self.irq_pending = false;
+      }
+      0xe000..=0xffff => self.irq_enabled = true,
+
+      _ => {}
+    }
+  }
+
+  fn peek_chr(&self, addr: usize) -> u8 {
+    let bank = self.chr_bank_of(addr);
+    self.cartridge.read_chr(bank, 0x400, addr & 0x3ff)
+  }
+
+  fn write_chr(&mut self, addr: usize, val: u8) {
+    let bank = self.chr_bank_of(addr);
+    self.cartridge.write_chr(bank, 0x400, addr & 0x3ff, val);
+  }
+
+  fn mirroring(&self) -> Mirroring {
+    self.mirroring
+  }
+
+  fn irq(&self) -> bool {
+    self.irq_pending
+  }
+
+  fn ppu_address(&mut self, addr: usize) {
+    let a12 = addr & 0x1000 != 0;
+
+    if a12 {
+      if !self.a12 && self.a12_low_count >= A12_FILTER {
+        self.clock_irq_counter();
+      }
+      self.a12_low_count = 0;
+    } else if self.a12_low_count < A12_FILTER {
+      self.a12_low_count += 1;
+    }
+
+    self.a12 = a12;
+  }
+}
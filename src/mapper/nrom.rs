@@ -0,0 +1,54 @@
+use super::super::cartridge::Cartridge;
+use super::{Mapper, Mirroring};
+
+/// # NROM (Mapper 0)
+///
+/// バンク切り替えのない基板です
+/// 16KiB の PRG-ROM は $c000 ~ $ffff にミラーされます
+///
+/// https://wiki.nesdev.com/w/index.php/NROM
+pub struct Nrom {
+  cartridge: Cartridge,
+}
+
+impl Nrom {
+  pub fn new(cartridge: Cartridge) -> Self {
+    Self { cartridge }
+  }
+}
+
+impl Mapper for Nrom {
+  fn cartridge(&self) -> &Cartridge {
+    &self.cartridge
+  }
+
+  fn cartridge_mut(&mut self) -> &mut Cartridge {
+    &mut self.cartridge
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    match addr {
+      0x6000..=0x7fff => self.cartridge.read_prg_ram(addr),
+      0x8000..=0xffff => self.cartridge.read_prg(0, 0, addr - 0x8000),
+      _ => 0,
+    }
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    if let 0x6000..=0x7fff = addr {
+      self.cartridge.write_prg_ram(addr, val);
+    }
+  }
+
+  fn peek_chr(&self, addr: usize) -> u8 {
+    self.cartridge.read_chr(0, 0, addr)
+  }
+
+  fn write_chr(&mut self, addr: usize, val: u8) {
+    self.cartridge.write_chr(0, 0, addr, val);
+  }
+
+  fn mirroring(&self) -> Mirroring {
+    self.cartridge.header.mirroring
+  }
+}
@@ -0,0 +1,60 @@
+use super::super::cartridge::Cartridge;
+use super::{bus_conflict, Mapper, Mirroring};
+
+/// # UxROM (Mapper 2)
+///
+/// $8000 ~ $bfff を 16KiB 単位で切り替え、$c000 ~ $ffff は最後のバンクに固定されます
+///
+/// https://wiki.nesdev.com/w/index.php/UxROM
+pub struct Uxrom {
+  cartridge: Cartridge,
+  prg_bank: u8,
+}
+
+impl Uxrom {
+  pub fn new(cartridge: Cartridge) -> Self {
+    Self {
+      cartridge,
+      prg_bank: 0,
+    }
+  }
+}
+
+impl Mapper for Uxrom {
+  fn cartridge(&self) -> &Cartridge {
+    &self.cartridge
+  }
+
+  fn cartridge_mut(&mut self) -> &mut Cartridge {
+    &mut self.cartridge
+  }
+
+  fn peek(&self, addr: usize) -> u8 {
+    match addr {
+      0x8000..=0xbfff => self.cartridge.read_prg(self.prg_bank as usize, 0x4000, addr & 0x3fff),
+      0xc000..=0xffff => {
+        let last = (self.cartridge.prg_rom.len() / 0x4000).saturating_sub(1);
+        self.cartridge.read_prg(last, 0x4000, addr & 0x3fff)
+      }
+      _ => 0,
+    }
+  }
+
+  fn write(&mut self, addr: usize, val: u8) {
+    if let 0x8000..=0xffff = addr {
+      self.prg_bank = bus_conflict(self, addr, val);
+    }
+  }
+
+  fn peek_chr(&self, addr: usize) -> u8 {
+    self.cartridge.read_chr(0, 0, addr)
+  }
+
+  fn write_chr(&mut self, addr: usize, val: u8) {
+    self.cartridge.write_chr(0, 0, addr, val);
+  }
+
+  fn mirroring(&self) -> Mirroring {
+    self.cartridge.header.mirroring
+  }
+}
@@ -1,16 +1,677 @@
-use super::machine;
+use super::mapper::{Mapper, Mirroring};
 
-#[derive(Debug, Copy, Clone)]
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+const OAM_SIZE: usize = 0x100; // 64 スプライト x 4 バイト
+const SPRITE_LIMIT: usize = 8; // 1ラインに表示できるスプライトの数
+const NAME_TABLE_SIZE: usize = 0x400; // 32 x 30 タイル + 属性テーブル 64 バイト
+const PALETTE_SIZE: usize = 0x20; // 背景 16 色 + スプライト 16 色
+
+// PPUCTRL ($2000)
+const CTRL_INCREMENT: u8 = 0x04; // VRAM アドレスを 32 ずつ進める
+const CTRL_SPRITE_TABLE: u8 = 0x08; // 8x8 スプライトのパターンテーブル ($0000 / $1000)
+const CTRL_BG_TABLE: u8 = 0x10; // 背景のパターンテーブル ($0000 / $1000)
+const CTRL_SPRITE_SIZE: u8 = 0x20; // 8x16 スプライト
+const CTRL_NMI: u8 = 0x80; // VBlank で NMI を起こす
+
+// PPUMASK ($2001)
+const MASK_GRAYSCALE: u8 = 0x01;
+const MASK_BG_LEFT: u8 = 0x02; // 左端 8 ピクセルにも背景を表示する
+const MASK_SPRITE_LEFT: u8 = 0x04; // 左端 8 ピクセルにもスプライトを表示する
+const MASK_BG: u8 = 0x08; // 背景を表示する
+const MASK_SPRITE: u8 = 0x10; // スプライトを表示する
+
+// PPUSTATUS ($2002)
+const STATUS_OVERFLOW: u8 = 0x20; // 1ラインに 9 個以上のスプライトがある
+const STATUS_SPRITE_ZERO: u8 = 0x40; // スプライト 0 と背景の不透明なピクセルが重なった
+const STATUS_VBLANK: u8 = 0x80;
+
+// OAM の属性 (3バイト目)
+const ATTR_PALETTE: u8 = 0x03;
+const ATTR_BEHIND: u8 = 0x20; // 背景の後ろに表示する
+const ATTR_FLIP_H: u8 = 0x40;
+const ATTR_FLIP_V: u8 = 0x80;
+
+// ライン番号 (NTSC)
+const VBLANK_LINE: usize = 241;
+const PRE_RENDER_LINE: usize = 261;
+pub const LINES: usize = 262; // 1フレームのライン数
+pub const DOTS: usize = 341; // 1ラインのドット数 (CPU 1サイクルで 3 ドット進む)
+
+// パレットの色番号 ($00 ~ $3f) と RGB の対応
+// https://wiki.nesdev.com/w/index.php/PPU_palettes
+#[rustfmt::skip]
+pub const COLORS: [[u8; 3]; 64] = [
+  [0x66, 0x66, 0x66], [0x00, 0x2a, 0x88], [0x14, 0x12, 0xa7], [0x3b, 0x00, 0xa4],
+  [0x5c, 0x00, 0x7e], [0x6e, 0x00, 0x40], [0x6c, 0x06, 0x00], [0x56, 0x1d, 0x00],
+  [0x33, 0x35, 0x00], [0x0b, 0x48, 0x00], [0x00, 0x52, 0x00], [0x00, 0x4f, 0x08],
+  [0x00, 0x40, 0x4d], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+  [0xad, 0xad, 0xad], [0x15, 0x5f, 0xd9], [0x42, 0x40, 0xff], [0x75, 0x27, 0xfe],
+  [0xa0, 0x1a, 0xcc], [0xb7, 0x1e, 0x7b], [0xb5, 0x31, 0x20], [0x99, 0x4e, 0x00],
+  [0x6b, 0x6d, 0x00], [0x38, 0x87, 0x00], [0x0c, 0x93, 0x00], [0x00, 0x8f, 0x32],
+  [0x00, 0x7c, 0x8d], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+  [0xff, 0xfe, 0xff], [0x64, 0xb0, 0xff], [0x92, 0x90, 0xff], [0xc6, 0x76, 0xff],
+  [0xf3, 0x6a, 0xff], [0xfe, 0x6e, 0xcc], [0xfe, 0x81, 0x70], [0xea, 0x9e, 0x22],
+  [0xbc, 0xbe, 0x00], [0x88, 0xd8, 0x00], [0x5c, 0xe4, 0x30], [0x45, 0xe0, 0x82],
+  [0x48, 0xcd, 0xde], [0x4f, 0x4f, 0x4f], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+  [0xff, 0xfe, 0xff], [0xc0, 0xdf, 0xff], [0xd3, 0xd2, 0xff], [0xe8, 0xc8, 0xff],
+  [0xfb, 0xc2, 0xff], [0xfe, 0xc4, 0xea], [0xfe, 0xcc, 0xc5], [0xf7, 0xd8, 0xa5],
+  [0xe4, 0xe5, 0x94], [0xcf, 0xef, 0x96], [0xbd, 0xf4, 0xab], [0xb3, 0xf3, 0xcc],
+  [0xb5, 0xeb, 0xf2], [0xb8, 0xb8, 0xb8], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+/// # PPU
+///
+/// CPU から $2000 ~ $2007 (8バイトごとにミラー) で見えるレジスタを持ちます
+///
+/// | Address | Name | Access | Description |
+/// | - | - | - | - |
+/// | $2000 | PPUCTRL | W | NMI の有効化, VRAM アドレスの増分, ネームテーブルの選択など |
+/// | $2001 | PPUMASK | W | 背景, スプライトの表示 |
+/// | $2002 | PPUSTATUS | R | VBlank など。読むと VBlank のビットと書き込みトグルが下りる |
+/// | $2003 | OAMADDR | W | OAM のアドレス |
+/// | $2004 | OAMDATA | RW | OAM の読み書き (書き込むとアドレスが進む) |
+/// | $2005 | PPUSCROLL | Wx2 | スクロール位置 (X, Y の順) |
+/// | $2006 | PPUADDR | Wx2 | VRAM アドレス (上位, 下位の順) |
+/// | $2007 | PPUDATA | RW | VRAM の読み書き (読み出しは1回遅れる) |
+///
+/// $2005, $2006 は書き込みトグル `w` を共有していて,
+/// スクロールとアドレスは内部レジスタ `v`, `t`, `x` にまとめて保持されます
+///
+/// PPU のアドレス空間は次の通りです
+///
+/// | Address | Size | Description |
+/// | - | - | - |
+/// | $0000 ~ $1fff | $2000 | パターンテーブル (カセットの CHR-ROM, CHR-RAM) |
+/// | $2000 ~ $2fff | $1000 | ネームテーブル (ミラーリングに応じて VRAM に割り当てる) |
+/// | $3000 ~ $3eff | $0f00 | $2000 ~ $2eff のミラー |
+/// | $3f00 ~ $3f1f | $0020 | パレット ($3f10, $3f14, $3f18, $3f1c は $3f00, $3f04, $3f08, $3f0c と共有) |
+/// | $3f20 ~ $3fff | - | パレットのミラー |
+///
+/// https://wiki.nesdev.com/w/index.php/PPU_registers
+/// https://wiki.nesdev.com/w/index.php/PPU_scrolling
+/// https://wiki.nesdev.com/w/index.php/PPU_memory_map
+///
+/// `exec` を呼ぶたびに1ドット進み, 1フレームは 341 ドット x 262 ラインです (NTSC)
+///
+/// | Line | Description |
+/// | - | - |
+/// | 0 ~ 239 | 表示ライン (ドット 1 ~ 256 で1ピクセルずつ `frame` に描画する) |
+/// | 240 | ポストレンダーライン |
+/// | 241 ~ 260 | VBlank (241 ラインのドット 1 で VBlank フラグが立ち, NMI が有効なら NMI) |
+/// | 261 | プリレンダーライン (ドット 1 でフラグを下ろす. 描画中の奇数フレームは最後のドットを飛ばす) |
+///
+/// 表示ラインとプリレンダーラインでは次のドットでメモリを読みます
+///
+/// | Dot | Description |
+/// | - | - |
+/// | 1 ~ 256 | 背景のタイル (8 ドットでネームテーブル, 属性テーブル, パターン下位, 上位) |
+/// | 257 | 次のラインのスプライトを選んでパターンを読む |
+/// | 321 ~ 336 | 次のラインの先頭2タイル |
+///
+/// 背景はシフトレジスタに積んで1ドットずつ取り出すので, ライン途中の PPUMASK の書き込みも反映されます。
+/// `frame` は 256x240 の RGB です
+///
+/// https://wiki.nesdev.com/w/index.php/PPU_frame_timing
+///
+/// OAM の1スプライトは4バイトで, 1ラインに表示できるのは OAM の先頭から 8 個までです
+///
+/// | Byte | Description |
+/// | - | - |
+/// | 0 | Y 座標 - 1 |
+/// | 1 | タイル番号 (8x16 のときは bit 0 がパターンテーブル) |
+/// | 2 | 属性 (bit 0-1: パレット, bit 5: 背景の後ろ, bit 6: 左右反転, bit 7: 上下反転) |
+/// | 3 | X 座標 |
+///
+/// https://wiki.nesdev.com/w/index.php/PPU_OAM
+#[derive(Debug, Clone)]
 pub struct Ppu {
+  pub ctrl: u8,
+  pub mask: u8,
+  pub status: u8,
+  pub oam_addr: u8,
+  pub oam: [u8; OAM_SIZE],
+
+  /// 物理ネームテーブル (0, 1 は本体の VRAM, 2, 3 は4画面のときのカセット側の VRAM)
+  pub nametable: [[u8; NAME_TABLE_SIZE]; 4],
+  pub palette: [u8; PALETTE_SIZE],
+
+  /// 現在の VRAM アドレス (15bit)
+  pub v: u16,
+  /// 一時的な VRAM アドレス (スクロール位置)
+  pub t: u16,
+  /// 横方向の細かいスクロール位置 (3bit)
+  pub x: u8,
+  /// $2005, $2006 の書き込みトグル (false = 1回目)
+  pub w: bool,
+
+  /// 描画した画面 (1ピクセル RGB 3 バイト)
+  pub frame: Vec<u8>,
+
+  /// 現在のライン (0 ~ 261)
+  pub line: usize,
+  /// 現在のドット (0 ~ 340)
+  pub dot: usize,
+  odd_frame: bool,   // 奇数フレームか
+  frame_ready: bool, // VBlank に入ってから take_frame で取り出されたか
+
+  // 背景のフェッチ (次のタイル)
+  next_tile: u8,
+  next_attribute: u8,
+  next_low: u8,
+  next_high: u8,
+  // 背景のシフトレジスタ (上位 8bit が表示中のタイル)
+  bg_low: u16,
+  bg_high: u16,
+  attribute_low: u16,
+  attribute_high: u16,
+
+  // 次のラインに表示するスプライト
+  sprites: [Sprite; SPRITE_LIMIT],
+  sprite_count: usize,
+
+  read_buffer: u8, // $2007 の読み出しバッファ
+  latch: u8,       // 最後にレジスタに書き込まれた値 (書き込み専用レジスタを読むと見える)
 }
 
 impl Ppu {
   pub fn new() -> Self {
     Self {
+      ctrl: 0,
+      mask: 0,
+      status: 0,
+      oam_addr: 0,
+      oam: [0; OAM_SIZE],
+
+      nametable: [[0; NAME_TABLE_SIZE]; 4],
+      palette: [0; PALETTE_SIZE],
+
+      v: 0,
+      t: 0,
+      x: 0,
+      w: false,
+
+      frame: vec![0; WIDTH * HEIGHT * 3],
+
+      line: 0,
+      dot: 0,
+      odd_frame: false,
+      frame_ready: false,
+
+      next_tile: 0,
+      next_attribute: 0,
+      next_low: 0,
+      next_high: 0,
+      bg_low: 0,
+      bg_high: 0,
+      attribute_low: 0,
+      attribute_high: 0,
+
+      sprites: [Sprite::default(); SPRITE_LIMIT],
+      sprite_count: 0,
+
+      read_buffer: 0,
+      latch: 0,
+    }
+  }
+
+  // 1ドット進める
+  // https://wiki.nesdev.com/w/index.php/PPU_rendering
+  pub fn exec(&mut self, mapper: &mut Option<Box<dyn Mapper>>) {
+    let visible = self.line < HEIGHT;
+
+    if (visible || self.line == PRE_RENDER_LINE) && self.rendering() {
+      self.fetch(mapper);
+    }
+
+    match (self.line, self.dot) {
+      (VBLANK_LINE, 1) => {
+        self.start_vblank();
+        self.frame_ready = true;
+      }
+      (PRE_RENDER_LINE, 1) => {
+        self.end_vblank();
+        self.status &= !(STATUS_SPRITE_ZERO | STATUS_OVERFLOW);
+      }
+      _ => {}
+    }
+
+    if visible && (1..=WIDTH).contains(&self.dot) {
+      self.render_pixel(self.dot - 1);
+    }
+
+    self.dot += 1;
+
+    // 描画が有効なら奇数フレームはプリレンダーラインの最後のドットを飛ばす
+    if self.line == PRE_RENDER_LINE && self.dot == DOTS - 1 && self.odd_frame && self.rendering() {
+      self.dot = DOTS;
+    }
+
+    if self.dot == DOTS {
+      self.dot = 0;
+      self.line += 1;
+      if self.line == LINES {
+        self.line = 0;
+        self.odd_frame = !self.odd_frame;
+      }
+    }
+  }
+
+  // 前回の呼び出しから VBlank に入ったか (frame が描き上がったか)
+  pub fn take_frame(&mut self) -> bool {
+    let ready = self.frame_ready;
+    self.frame_ready = false;
+    ready
+  }
+
+  // 背景かスプライトの表示が有効か
+  fn rendering(&self) -> bool {
+    self.mask & (MASK_BG | MASK_SPRITE) != 0
+  }
+
+  // ドットに応じて背景のタイル, スプライトを読んでスクロールを進める
+  fn fetch(&mut self, mapper: &mut Option<Box<dyn Mapper>>) {
+    let dot = self.dot;
+
+    if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+      self.shift_background();
+
+      match (dot - 1) % 8 {
+        0 => {
+          self.load_background();
+          self.next_tile = self.read_vram(0x2000 | (self.v & 0x0fff), mapper);
+        }
+        2 => {
+          let v = self.v;
+          let attribute = self.read_vram(0x23c0 | (v & 0x0c00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07), mapper);
+          // 属性テーブルの1バイトは 4x4 タイルで, 2x2 タイルごとに 2bit
+          let shift = ((v >> 4) & 0x04) | (v & 0x02);
+          self.next_attribute = (attribute >> shift) & 0x03;
+        }
+        4 => self.next_low = self.read_vram(self.background_address(), mapper),
+        6 => self.next_high = self.read_vram(self.background_address() + 8, mapper),
+        7 => self.increment_x(),
+        _ => {}
+      }
+    }
+
+    match dot {
+      256 => self.increment_y(),
+      257 => {
+        // スクロールの横方向を t から v に戻す
+        self.v = (self.v & 0x7be0) | (self.t & 0x041f);
+        self.fetch_sprites(mapper);
+      }
+      // スクロールの縦方向を t から v に戻す
+      280..=304 if self.line == PRE_RENDER_LINE => self.v = (self.v & 0x041f) | (self.t & 0x7be0),
+      _ => {}
     }
   }
 
-  pub fn exec(machine: machine::Machine) {
-    // レジスタの内容をもとに色の情報などを渡す
+  // 次のタイルの fine Y 行目のパターンのアドレス
+  fn background_address(&self) -> u16 {
+    let table = if self.ctrl & CTRL_BG_TABLE == 0 { 0x0000 } else { 0x1000 };
+    table + self.next_tile as u16 * 16 + ((self.v >> 12) & 0x07)
+  }
+
+  // 読んでおいたタイルをシフトレジスタの下位 8bit に積む
+  fn load_background(&mut self) {
+    self.bg_low = (self.bg_low & 0xff00) | self.next_low as u16;
+    self.bg_high = (self.bg_high & 0xff00) | self.next_high as u16;
+    let fill = |bit: u8| if bit != 0 { 0xff } else { 0x00 };
+    self.attribute_low = (self.attribute_low & 0xff00) | fill(self.next_attribute & 0x01);
+    self.attribute_high = (self.attribute_high & 0xff00) | fill(self.next_attribute & 0x02);
+  }
+
+  fn shift_background(&mut self) {
+    self.bg_low <<= 1;
+    self.bg_high <<= 1;
+    self.attribute_low <<= 1;
+    self.attribute_high <<= 1;
+  }
+
+  // 次のラインに表示するスプライトを OAM から選んでパターンを読む
+  // OAM の Y 座標は表示位置より 1 小さい (前のラインで選んだものを表示するため)
+  fn fetch_sprites(&mut self, mapper: &mut Option<Box<dyn Mapper>>) {
+    let height = if self.ctrl & CTRL_SPRITE_SIZE == 0 { 8 } else { 16 };
+
+    // プリレンダーラインでは選ばない (0 ラインにスプライトは出ない)
+    let mut found = [0; SPRITE_LIMIT];
+    let mut count = 0;
+    if self.line != PRE_RENDER_LINE {
+      for n in 0..OAM_SIZE / 4 {
+        let row = self.line.wrapping_sub(self.oam[n * 4] as usize);
+        if row < height {
+          if count == SPRITE_LIMIT {
+            self.status |= STATUS_OVERFLOW;
+            break;
+          }
+          found[count] = n;
+          count += 1;
+        }
+      }
+    }
+
+    // 空いている枠もタイル $ff を読む (MMC3 は A12 の変化でラインを数える)
+    for (slot, &n) in found.iter().enumerate() {
+      if slot >= count {
+        let addr = self.sprite_address(0xff, 0);
+        self.read_vram(addr, mapper);
+        self.read_vram(addr + 8, mapper);
+        continue;
+      }
+
+      let (y, tile, attribute, x) = (self.oam[n * 4], self.oam[n * 4 + 1], self.oam[n * 4 + 2], self.oam[n * 4 + 3]);
+      let mut row = self.line - y as usize;
+      if attribute & ATTR_FLIP_V != 0 {
+        row = height - 1 - row;
+      }
+      let addr = self.sprite_address(tile, row);
+      self.sprites[slot] = Sprite {
+        x,
+        low: self.read_vram(addr, mapper),
+        high: self.read_vram(addr + 8, mapper),
+        attribute,
+        zero: n == 0,
+      };
+    }
+    self.sprite_count = count;
+  }
+
+  // 背景とスプライトを重ねて1ピクセル描画する
+  fn render_pixel(&mut self, x: usize) {
+    let mut bg = 0;
+    if self.mask & MASK_BG != 0 && (x >= 8 || self.mask & MASK_BG_LEFT != 0) {
+      let bit = 0x8000 >> self.x;
+      let pattern = (self.bg_low & bit != 0) as u8 | (((self.bg_high & bit != 0) as u8) << 1);
+      let palette = (self.attribute_low & bit != 0) as u8 | (((self.attribute_high & bit != 0) as u8) << 1);
+      if pattern != 0 {
+        bg = (palette << 2) | pattern;
+      }
+    }
+
+    // OAM の先頭に近いスプライトが優先される
+    let mut sprite = None;
+    if self.mask & MASK_SPRITE != 0 && (x >= 8 || self.mask & MASK_SPRITE_LEFT != 0) {
+      sprite = self.sprites[..self.sprite_count].iter().find_map(|sprite| {
+        let offset = x.wrapping_sub(sprite.x as usize);
+        if offset >= 8 {
+          return None;
+        }
+        let shift = if sprite.attribute & ATTR_FLIP_H == 0 { 7 - offset } else { offset };
+        let pattern = ((sprite.low >> shift) & 1) | (((sprite.high >> shift) & 1) << 1);
+        if pattern == 0 {
+          None
+        } else {
+          Some((0x10 | ((sprite.attribute & ATTR_PALETTE) << 2) | pattern, sprite))
+        }
+      });
+    }
+
+    let pixel = match sprite {
+      Some((pixel, sprite)) => {
+        if sprite.zero && bg != 0 && x != WIDTH - 1 {
+          self.status |= STATUS_SPRITE_ZERO;
+        }
+        if sprite.attribute & ATTR_BEHIND == 0 || bg == 0 {
+          pixel
+        } else {
+          bg
+        }
+      }
+      None => bg, // 0 なら背景色 ($3f00)
+    };
+
+    let color = self.color(pixel);
+    let offset = (self.line * WIDTH + x) * 3;
+    self.frame[offset..offset + 3].copy_from_slice(&COLORS[color as usize]);
+  }
+
+  // スプライトのタイルの row 行目のパターンのアドレス
+  fn sprite_address(&self, tile: u8, row: usize) -> u16 {
+    if self.ctrl & CTRL_SPRITE_SIZE == 0 {
+      let table = if self.ctrl & CTRL_SPRITE_TABLE == 0 { 0x0000 } else { 0x1000 };
+      table + tile as u16 * 16 + row as u16
+    } else {
+      // 8x16 は bit 0 でパターンテーブルを選び, 上下2タイルを続けて使う
+      let table = (tile as u16 & 0x01) * 0x1000;
+      let tile = (tile as u16 & 0xfe) + (row / 8) as u16;
+      table + tile * 16 + (row % 8) as u16
+    }
+  }
+
+  // パレット番号をカラー番号 ($00 ~ $3f) にする
+  fn color(&self, pixel: u8) -> u8 {
+    let color = self.palette[palette_index(0x3f00 | pixel as u16)];
+    if self.mask & MASK_GRAYSCALE != 0 {
+      color & 0x30
+    } else {
+      color
+    }
+  }
+
+  // coarse X を進める (32 タイルで横のネームテーブルに移る)
+  fn increment_x(&mut self) {
+    if self.v & 0x001f == 31 {
+      self.v = (self.v & !0x001f) ^ 0x0400;
+    } else {
+      self.v += 1;
+    }
+  }
+
+  // fine Y, coarse Y を進める (30 タイルで縦のネームテーブルに移る)
+  fn increment_y(&mut self) {
+    if self.v & 0x7000 != 0x7000 {
+      self.v += 0x1000;
+      return;
+    }
+
+    self.v &= !0x7000;
+    let coarse_y = match (self.v & 0x03e0) >> 5 {
+      29 => {
+        self.v ^= 0x0800;
+        0
+      }
+      31 => 0, // 属性テーブルを指していたときは縦のネームテーブルは切り替わらない
+      y => y + 1,
+    };
+    self.v = (self.v & !0x03e0) | (coarse_y << 5);
+  }
+
+  // NMI の信号 (VBlank 中で PPUCTRL の bit 7 が立っている間)
+  pub fn nmi(&self) -> bool {
+    self.ctrl & CTRL_NMI != 0 && self.status & STATUS_VBLANK != 0
+  }
+
+  pub fn start_vblank(&mut self) {
+    self.status |= STATUS_VBLANK;
+  }
+
+  pub fn end_vblank(&mut self) {
+    self.status &= !STATUS_VBLANK;
+  }
+
+  // $2000 ~ $2007 の書き込み
+  pub fn write_register(&mut self, addr: usize, val: u8, mapper: &mut Option<Box<dyn Mapper>>) {
+    self.latch = val;
+
+    match addr % 8 {
+      0 => {
+        self.ctrl = val;
+        // ネームテーブルの選択は t の bit 10, 11
+        self.t = (self.t & 0xf3ff) | ((val as u16 & 0x03) << 10);
+      }
+
+      1 => self.mask = val,
+
+      3 => self.oam_addr = val,
+
+      4 => {
+        self.oam[self.oam_addr as usize] = val;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+      }
+
+      5 => {
+        if self.w {
+          // 2回目: Y (上位5bit は coarse Y, 下位3bit は fine Y)
+          self.t = (self.t & 0x8c1f) | ((val as u16 & 0xf8) << 2) | ((val as u16 & 0x07) << 12);
+        } else {
+          // 1回目: X (上位5bit は coarse X, 下位3bit は fine X)
+          self.t = (self.t & 0xffe0) | (val as u16 >> 3);
+          self.x = val & 0x07;
+        }
+        self.w = !self.w;
+      }
+
+      6 => {
+        // 2回書き込む (1回目上位6bit, 2回目下位8bit)
+        if self.w {
+          self.t = (self.t & 0xff00) | val as u16;
+          self.v = self.t;
+        } else {
+          self.t = (self.t & 0x00ff) | ((val as u16 & 0x3f) << 8);
+        }
+        self.w = !self.w;
+      }
+
+      7 => {
+        self.write_vram(self.v, val, mapper);
+        self.increment_v();
+      }
+
+      _ => {} // PPUSTATUS は読み出し専用
+    }
+  }
+
+  // $2000 ~ $2007 の読み出し
+  pub fn read_register(&mut self, addr: usize, mapper: &mut Option<Box<dyn Mapper>>) -> u8 {
+    match addr % 8 {
+      2 => {
+        // 下位5bit は最後に書き込まれた値が見える
+        let val = (self.status & 0xe0) | (self.latch & 0x1f);
+        self.end_vblank();
+        self.w = false;
+        val
+      }
+
+      4 => self.oam[self.oam_addr as usize],
+
+      7 => {
+        // 読み出しはバッファを経由するので1回遅れる
+        // パレットだけはすぐに読めて, バッファには下にあるネームテーブルが入る
+        let val = if self.v & 0x3fff >= 0x3f00 {
+          self.read_buffer = self.read_vram(self.v - 0x1000, mapper);
+          self.palette[palette_index(self.v)]
+        } else {
+          let val = self.read_buffer;
+          self.read_buffer = self.read_vram(self.v, mapper);
+          val
+        };
+        self.increment_v();
+        val
+      }
+
+      _ => self.latch,
+    }
+  }
+
+  // read_register と同じ値を状態を変えずに返す
+  pub fn peek_register(&self, addr: usize, mapper: &Option<Box<dyn Mapper>>) -> u8 {
+    match addr % 8 {
+      2 => (self.status & 0xe0) | (self.latch & 0x1f),
+      4 => self.oam[self.oam_addr as usize],
+      7 if self.v & 0x3fff >= 0x3f00 => self.peek_vram(self.v, mapper),
+      7 => self.read_buffer,
+      _ => self.latch,
+    }
+  }
+
+  // PPUCTRL の bit 2 が立っていたら 32 (1行) ずつ進める
+  fn increment_v(&mut self) {
+    let increment = if self.ctrl & CTRL_INCREMENT == 0 { 1 } else { 32 };
+    self.v = self.v.wrapping_add(increment) & 0x7fff;
+  }
+
+  // PPU のアドレス空間 ($0000 ~ $3fff)
+  pub fn read_vram(&mut self, addr: u16, mapper: &mut Option<Box<dyn Mapper>>) -> u8 {
+    let addr = (addr & 0x3fff) as usize;
+    if let Some(mapper) = mapper {
+      mapper.ppu_address(addr);
+    }
+    match addr {
+      0x0000..=0x1fff => match mapper {
+        Some(mapper) => mapper.read_chr(addr),
+        None => 0,
+      },
+      _ => self.peek_vram(addr as u16, mapper),
+    }
+  }
+
+  // read_vram と同じ値を状態を変えずに返す (マッパーにアドレスを通知しない)
+  pub fn peek_vram(&self, addr: u16, mapper: &Option<Box<dyn Mapper>>) -> u8 {
+    let addr = (addr & 0x3fff) as usize;
+    match addr {
+      0x0000..=0x1fff => match mapper {
+        Some(mapper) => mapper.peek_chr(addr),
+        None => 0,
+      },
+      0x2000..=0x3eff => {
+        let (table, offset) = nametable_address(addr, mapper);
+        self.nametable[table][offset]
+      }
+      _ => self.palette[palette_index(addr as u16)],
+    }
+  }
+
+  fn write_vram(&mut self, addr: u16, val: u8, mapper: &mut Option<Box<dyn Mapper>>) {
+    let addr = (addr & 0x3fff) as usize;
+    if let Some(mapper) = mapper {
+      mapper.ppu_address(addr);
+    }
+    match addr {
+      0x0000..=0x1fff => {
+        if let Some(mapper) = mapper {
+          mapper.write_chr(addr, val);
+        }
+      }
+      0x2000..=0x3eff => {
+        let (table, offset) = nametable_address(addr, mapper);
+        self.nametable[table][offset] = val;
+      }
+      _ => self.palette[palette_index(addr as u16)] = val & 0x3f, // 色は 6bit
+    }
+  }
+}
+
+// 次のラインに表示するスプライト
+#[derive(Debug, Default, Copy, Clone)]
+struct Sprite {
+  x: u8,
+  low: u8,  // パターンの下位 (上下反転は済ませてある)
+  high: u8, // パターンの上位
+  attribute: u8,
+  zero: bool, // スプライト 0 か
+}
+
+// $2000 ~ $3eff を (物理ネームテーブル, オフセット) にする
+// カセットが無いときは水平ミラーとして扱う
+fn nametable_address(addr: usize, mapper: &Option<Box<dyn Mapper>>) -> (usize, usize) {
+  let mirroring = match mapper {
+    Some(mapper) => mapper.mirroring(),
+    None => Mirroring::Horizontal,
+  };
+  let addr = (addr - 0x2000) % 0x1000;
+  (mirroring.nametable(addr / NAME_TABLE_SIZE), addr % NAME_TABLE_SIZE)
+}
+
+// $3f00 ~ $3fff をパレットの添字にする
+// スプライトの色 0 ($3f10, $3f14, $3f18, $3f1c) は背景の色 0 と共有している
+fn palette_index(addr: u16) -> usize {
+  let index = addr as usize % PALETTE_SIZE;
+  if index & 0x13 == 0x10 {
+    index & 0x0f
+  } else {
+    index
   }
 }
@@ -1,136 +1,137 @@
-use super::cpu;
+use super::cartridge::{Cartridge, LoadError};
+use super::disassembler;
+use super::instruction;
 use super::machine;
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::Read;
+use std::path::{Path, PathBuf};
 
-pub enum Ines {
-  Data(usize, Vec<u8>),
-}
-
-pub enum Roms {
-  Data([u8; 0x8000], [u8; 0x2000]),
-}
-
-pub fn read_nes(path: String) -> Ines {
+pub fn read_nes(path: String) -> io::Result<Vec<u8>> {
   println!("Target: {}", path);
-  let mut file = File::open(path).unwrap();
+  let mut file = File::open(path)?;
   let mut buffer: Vec<u8> = Vec::new();
+  file.read_to_end(&mut buffer)?;
 
-  Ines::Data(file.read_to_end(&mut buffer).unwrap(), buffer)
+  Ok(buffer)
 }
 
 pub fn debug(val: u8, eol: bool) {
   print!("{:>04x}{}", val, if eol { "\n" } else { " " });
 }
 
-pub fn load_cassette(machine: &mut machine::Machine, path: String, mode: bool) -> Result<([u8; 0x8000], [u8; 0x2000]), String> {
-  let mut prg_rom = [0; 0x8000];
-  let mut chr_rom = [0; 0x2000];
-  let ines = read_nes(path);
-
-  match ines {
-    Ines::Data(file, buffer) => {
-      // "NES" Header
-      if buffer[0] != 0x4e {
-        return Err("expect 0x4e".to_string());
-      }
-      if buffer[1] != 0x45 {
-        return Err("expect 0x45".to_string());
-      }
-      if buffer[2] != 0x53 {
-        return Err("expect 0x53".to_string());
-      }
-      if buffer[3] != 0x1a {
-        return Err("expect 0x1a".to_string());
+pub fn load_cassette(path: String, mode: bool) -> Result<Cartridge, LoadError> {
+  let buffer = read_nes(path)?;
+  let cartridge = Cartridge::from_bytes(&buffer)?;
+  let header = &cartridge.header;
+
+  let prg_bytes = header.prg_rom_bytes;
+  let chr_bytes = header.chr_rom_bytes;
+
+  let prg_addr = header.prg_offset();
+  let chr_addr = header.chr_offset();
+  println!("Format: \x1b[38;5;51m{:?}\x1b[m ({:?})", header.format, header.timing);
+  println!("PRG-ROM bytes: \x1b[38;5;51m0x{:>08x}\x1b[m", prg_bytes);
+  println!("CHR-ROM bytes: \x1b[38;5;51m0x{:>08x}\x1b[m", chr_bytes);
+  println!(
+    "PRG-RAM bytes: \x1b[38;5;51m0x{:>08x}\x1b[m (battery-backed 0x{:>08x}, {})",
+    header.prg_ram_bytes,
+    header.prg_nvram_bytes,
+    if header.battery { "battery" } else { "no battery" }
+  );
+  println!(
+    "CHR-RAM bytes: \x1b[38;5;51m0x{:>08x}\x1b[m (battery-backed 0x{:>08x})",
+    header.chr_ram_bytes, header.chr_nvram_bytes
+  );
+  println!(
+    "Mapper: \x1b[38;5;51m{}.{}\x1b[m ({:?})",
+    header.mapper, header.submapper, header.mirroring
+  );
+  println!(
+    "PRG-ROM mapping: \x1b[38;5;51m0x{:>08x} ~ 0x{:>08x}\x1b[m",
+    prg_addr,
+    prg_addr + prg_bytes
+  );
+  println!(
+    "CHR-ROM mapping: \x1b[38;5;51m0x{:>08x} ~ 0x{:>08x}\x1b[m",
+    chr_addr,
+    chr_addr + chr_bytes
+  );
+
+  if false {
+    println!("Data of PRG-ROM");
+    for i in prg_addr..prg_addr + 30 {
+      let mut line = format!("{:<03x}", i);
+      for j in 0..9 {
+        line = format!("{} \x1b[38;5;69m{:>08x}\x1b[m", line, buffer[i + j]);
       }
+      println!("{}", line);
+    }
+    println!("...");
+  }
 
-      let prg_banks = buffer[4] as usize;
-      let chr_banks = buffer[5] as usize;
-      let prg_bytes = prg_banks * 0x4000;
-      let chr_bytes = chr_banks * 0x2000;
-
-      machine.prg_bytes = prg_bytes;
-      machine.chr_bytes = chr_bytes;
-
-      let header = 16;
-      let prg_addr = header;
-      let chr_addr = prg_addr + prg_bytes;
-      println!("PRG-ROM bytes: \x1b[38;5;51m0x{:>08x}\x1b[m", prg_bytes);
-      println!("CHR-ROM bytes: \x1b[38;5;51m0x{:>08x}\x1b[m", chr_bytes);
-      println!(
-        "PRG-ROM mapping: \x1b[38;5;51m0x{:>08x} ~ 0x{:>08x}\x1b[m",
-        prg_addr,
-        prg_addr + prg_bytes
-      );
-      println!(
-        "CHR-ROM mapping: \x1b[38;5;51m0x{:>08x} ~ 0x{:>08x}\x1b[m",
-        chr_addr,
-        chr_addr + chr_bytes
-      );
-
-      if false {
-        println!("Data of PRG-ROM");
-        for i in prg_addr..prg_addr + 30 {
-          let mut line = format!("{:<03x}", i);
-          for j in 0..9 {
-            line = format!("{} \x1b[38;5;69m{:>08x}\x1b[m", line, buffer[i + j]);
-          }
-          println!("{}", line);
-        }
-        println!("...");
-      }
+  if false {
+    println!("Disassembled data of PRG-ROM");
+    let prg_rom = &cartridge.prg_rom;
+    let mut i = 0;
+    for _ in 0..30 {
+      let pc = 0x8000 + i as u16;
+      let line = disassembler::disassemble(&prg_rom[i..], pc);
+      println!("{:<04x} \x1b[38;5;69m{}\x1b[m", pc, line);
+      i += instruction::decode(prg_rom[i]).bytes as usize;
+    }
+    println!("...");
+  }
 
-      if false {
-        let mut cpu = cpu::Cpu::new();
-        println!("Converted data of PRG-ROM");
-        for i in prg_addr..prg_addr + 30 {
-          let mut line = format!("{:<03x}", i);
-          for j in 0..3 {
-            line = format!(
-              "{} \x1b[38;5;69m{:?}\x1b[m",
-              line,
-              cpu.convert(buffer[i + j])
-            );
-          }
-          println!("{}", line);
-        }
-        println!("...");
-      }
+  if mode {
+    println!("\n========== PRG-ROM ==========");
+    for (addr, val) in cartridge.prg_rom.iter().enumerate() {
+      debug(*val, (addr + 1) % 17 == 0);
+    }
 
-      if false {
-        let mut cpu = cpu::Cpu::new();
-        for addr in prg_addr..prg_addr + prg_bytes {
-          cpu.convert(buffer[addr]);
-        }
-      }
+    println!("\n========== CHR-ROM ==========");
+    for (addr, val) in cartridge.chr_rom.iter().enumerate() {
+      debug(*val, (addr + 1) % 17 == 0);
+    }
+  }
 
-      let mut cpu = cpu::Cpu::new();
+  println!();
 
-      if mode {
-        println!("\n========== PRG-ROM ==========");
-      }
-      for addr in 0..prg_bytes {
-        prg_rom[addr] = buffer[prg_addr + addr];
-        if mode {
-          debug(prg_rom[addr], (addr + 1) % 17 == 0);
-        }
-      }
+  Ok(cartridge)
+}
 
-      if mode {
-        println!("\n========== CHR-ROM ==========");
-      }
-      for addr in 0..chr_bytes {
-        chr_rom[addr] = buffer[chr_addr + addr];
-        if mode {
-          debug(chr_rom[addr], (addr + 1) % 17 == 0);
-        }
-      }
+// セーブデータは ROM と同じ場所に拡張子 .sav で置く
+pub fn save_path(path: &str) -> PathBuf {
+  Path::new(path).with_extension("sav")
+}
 
-      println!();
+// バッテリーバックアップされた PRG-RAM をファイルから読み込む
+pub fn load_save(machine: &mut machine::Machine, path: &Path) -> io::Result<()> {
+  if !machine.battery() {
+    return Ok(());
+  }
+
+  match fs::read(path) {
+    Ok(data) => {
+      println!("Save: \x1b[38;5;51m{}\x1b[m", path.display());
+      machine.load_prg_ram(&data);
+      Ok(())
     }
+    // 初回起動時はまだ無い
+    Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+    Err(err) => Err(err),
+  }
+}
 
-    _ => panic!("Invalid file type"),
+// PRG-RAM が書き換えられていたらファイルに書き出す
+// 書き出しに失敗したときは次の呼び出しでもう一度書き出す
+pub fn write_save(machine: &mut machine::Machine, path: &Path) -> io::Result<()> {
+  if !machine.battery() || !machine.prg_ram_dirty() {
+    return Ok(());
   }
 
-  Ok((prg_rom, chr_rom))
+  fs::write(path, machine.prg_ram())?;
+  machine.clear_prg_ram_dirty();
+  Ok(())
 }